@@ -2,7 +2,7 @@ use std::time::Duration;
 
 use criterion::{Criterion, criterion_group};
 use rand::{Rng, thread_rng};
-use shute::{Buffer, BufferInit, BufferType, Device, Instance, LimitType};
+use shute::{Buffer, BufferInit, BufferType, Device, Features, Instance, LimitType};
 use wgpu::PowerPreference;
 
 fn generate_data(size: usize) -> Vec<u32> {
@@ -10,23 +10,44 @@ fn generate_data(size: usize) -> Vec<u32> {
     (0..size).map(|_| rng.r#gen()).collect()
 }
 
-fn initialize_gpu_buffer<'a>(device: &'a Device, data: &'a Vec<u32>) -> Buffer<'a> {
-    let buffer = device.create_buffer(
-        Some("test_send"),
-        BufferType::StorageBuffer {
-            output: true,
-            read_only: true,
-        },
-        BufferInit::WithData(data),
-    );
+fn initialize_gpu_buffer(device: &Device, data: &[u32]) -> Buffer<u32> {
+    let buffer = device
+        .create_buffer(
+            Some("test_send"),
+            BufferType::StorageBuffer {
+                output: true,
+                read_only: true,
+            },
+            BufferInit::WithData(data),
+        )
+        .expect("Failed to create buffer");
     device.synchronize();
     buffer
 }
 
-fn pull_data_from_buffer(buffer: &mut Buffer, output: &mut Vec<u32>) {
+fn initialize_gpu_buffer_pod(device: &Device, data: &[u32]) -> Buffer<u32> {
+    let buffer = device
+        .create_pod_buffer(
+            Some("test_send_pod"),
+            BufferType::StorageBuffer {
+                output: true,
+                read_only: true,
+            },
+            BufferInit::WithData(data),
+        )
+        .expect("Failed to create buffer");
+    device.synchronize();
+    buffer
+}
+
+fn pull_data_from_buffer(buffer: &mut Buffer<u32>, output: &mut Vec<u32>) {
     pollster::block_on(buffer.read(output)).expect("unable to read buffer");
 }
 
+fn pull_data_from_buffer_pod(buffer: &Buffer<u32>, output: &mut [u32]) {
+    pollster::block_on(buffer.read_pod(output)).expect("unable to read buffer");
+}
+
 fn io_oneshot(c: &mut Criterion) {
     let mut group = c.benchmark_group("CPU-GPU Data Transfer (One-Shot)");
     group
@@ -34,16 +55,20 @@ fn io_oneshot(c: &mut Criterion) {
         .noise_threshold(0.15)
         .measurement_time(Duration::from_secs(10));
     let instance = Instance::new();
-    let device = pollster::block_on(
-        instance.autoselect(PowerPreference::HighPerformance, LimitType::Downlevel),
-    )
+    let device = pollster::block_on(instance.autoselect(
+        PowerPreference::HighPerformance,
+        LimitType::Downlevel,
+        Features::empty(),
+    ))
     .unwrap();
     // Benchmarks for sending data from CPU to GPU
 
     let size = device.limits().max_buffer_size as usize / size_of::<u32>();
-    device.override_staging_size((size * size_of::<u32>()) as u32);
+    device
+        .reserve_staging((size * size_of::<u32>()) as u64)
+        .expect("Failed to create staging buffer");
     dbg!(size);
-    let mut buffer: Option<Buffer> = None;
+    let mut buffer: Option<Buffer<u32>> = None;
     let data = generate_data(size);
 
     group.bench_function("Buffer Initialization", |b| {
@@ -51,12 +76,20 @@ fn io_oneshot(c: &mut Criterion) {
             buffer = Some(initialize_gpu_buffer(&device, &data));
         })
     });
+    group.bench_function("Buffer Initialization (Pod)", |b| {
+        b.iter(|| {
+            buffer = Some(initialize_gpu_buffer_pod(&device, &data));
+        })
+    });
 
     // Benchmarks for pulling data from GPU to CPU
     let mut output = vec![0; size];
     group.bench_function("Reading Data from GPU Buffer", |b| {
         b.iter(|| pull_data_from_buffer(buffer.as_mut().unwrap(), &mut output))
     });
+    group.bench_function("Reading Data from GPU Buffer (Pod)", |b| {
+        b.iter(|| pull_data_from_buffer_pod(buffer.as_ref().unwrap(), &mut output))
+    });
 }
 fn io_tenshot(c: &mut Criterion) {
     let mut group = c.benchmark_group("CPU-GPU Data Transfer (10-Shot)");
@@ -65,9 +98,11 @@ fn io_tenshot(c: &mut Criterion) {
         .noise_threshold(0.15)
         .measurement_time(Duration::from_secs(10));
     let instance = Instance::new();
-    let device = pollster::block_on(
-        instance.autoselect(PowerPreference::HighPerformance, LimitType::Downlevel),
-    )
+    let device = pollster::block_on(instance.autoselect(
+        PowerPreference::HighPerformance,
+        LimitType::Downlevel,
+        Features::empty(),
+    ))
     .unwrap();
 
     // Benchmarks for sending data from CPU to GPU
@@ -1,6 +1,6 @@
 use criterion::{BenchmarkId, Criterion, criterion_group};
 use rand::Rng;
-use shute::{Buffer, BufferInit, BufferType, Instance, LimitType, PowerPreference, ShaderType};
+use shute::{BufferInit, BufferType, Features, Instance, LimitType, PowerPreference, RawBuffer};
 
 fn generate_data(dim: usize) -> Vec<f32> {
     let mut rng = rand::thread_rng();
@@ -11,59 +11,84 @@ fn generate_data(dim: usize) -> Vec<f32> {
     data
 }
 
-#[derive(ShaderType)]
-struct Input {
-    dim: u32,
-    nn: u32,
+// `ShaderType` generates unused assertion functions, which can only be allowed per module.
+#[allow(dead_code)]
+mod input {
+    use shute::ShaderType;
+
+    #[derive(ShaderType)]
+    pub struct Input {
+        pub dim: u32,
+        pub nn: u32,
+    }
 }
+use input::Input;
 
 fn compute(data: &mut Vec<f32>, dim: u32) {
     let nn = dim.div_ceil(64) * 64;
     let instance = Instance::new();
-    let device = pollster::block_on(
-        instance.autoselect(PowerPreference::HighPerformance, LimitType::Highest),
-    )
+    let device = pollster::block_on(instance.autoselect(
+        PowerPreference::HighPerformance,
+        LimitType::Highest,
+        Features::empty(),
+    ))
     .unwrap();
 
-    let mut input_buffer = device.create_buffer(
-        Some("input"),
-        BufferType::StorageBuffer {
-            output: true,
-            read_only: false,
-        },
-        BufferInit::<f32>::WithSize((nn * nn) as usize),
-    );
-    let mut input_buffer_t = device.create_buffer(
-        Some("input_t"),
-        BufferType::StorageBuffer {
-            output: true,
-            read_only: false,
-        },
-        BufferInit::<f32>::WithSize((nn * nn) as usize),
-    );
-    let mut output_buffer = device.create_buffer(
-        Some("output"),
-        BufferType::StorageBuffer {
-            output: true,
-            read_only: false,
-        },
-        BufferInit::WithData(data.to_owned()),
-    );
-    let mut param_buffer = device.create_buffer(
-        Some("params"),
-        BufferType::UniformBuffer,
-        BufferInit::WithData(Input { dim, nn }),
-    );
-    let groups: Vec<Vec<&mut Buffer>> = vec![vec![
-        &mut input_buffer,
-        &mut input_buffer_t,
-        &mut output_buffer,
-        &mut param_buffer,
+    let mut input_buffer = device
+        .create_buffer(
+            Some("input"),
+            BufferType::StorageBuffer {
+                output: true,
+                read_only: false,
+            },
+            BufferInit::<f32>::WithSize((nn * nn) as usize),
+        )
+        .expect("Failed to create buffer");
+    let mut input_buffer_t = device
+        .create_buffer(
+            Some("input_t"),
+            BufferType::StorageBuffer {
+                output: true,
+                read_only: false,
+            },
+            BufferInit::<f32>::WithSize((nn * nn) as usize),
+        )
+        .expect("Failed to create buffer");
+    let mut output_buffer = device
+        .create_buffer(
+            Some("output"),
+            BufferType::StorageBuffer {
+                output: true,
+                read_only: false,
+            },
+            BufferInit::WithData(data),
+        )
+        .expect("Failed to create buffer");
+    let mut param_buffer = device
+        .create_buffer(
+            Some("params"),
+            BufferType::UniformBuffer,
+            BufferInit::WithData(&[Input { dim, nn }]),
+        )
+        .expect("Failed to create buffer");
+    let groups: Vec<Vec<&mut RawBuffer>> = vec![vec![
+        &mut *input_buffer,
+        &mut *input_buffer_t,
+        &mut *output_buffer,
+        &mut *param_buffer,
     ]];
-    let padding_shader = device.create_shader_module(include_str!("padding.wgsl"), "main");
-    device.execute(&groups, padding_shader, [1, nn]);
-    let shader = device.create_shader_module(include_str!("shortcut.wgsl"), "main");
-    device.execute(&groups, shader, [nn / 64, nn / 64]);
+    let padding_shader = device
+        .create_shader_module(include_str!("padding.wgsl"), "main")
+        .expect("Failed to compile shader module");
+    device
+        .execute(&groups, &padding_shader, [1, nn])
+        .expect("Failed to execute shader");
+    let shader = device
+        .create_shader_module(include_str!("shortcut.wgsl"), "main")
+        .expect("Failed to compile shader module");
+    device
+        .execute(&groups, &shader, [nn / 64, nn / 64])
+        .expect("Failed to execute shader");
     pollster::block_on(output_buffer.read(data)).expect("Failed to fetch data from output buffer");
 }
 
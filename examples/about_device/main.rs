@@ -1,19 +1,23 @@
 //! Quick example for seeing what devices are returned through the `Instance::autoselect` method
-//! with all three power preferences, as well as all the devices returned through `Instance::devices`.
+//! with all three power preferences, as well as all the adapters returned through `Instance::adapters`.
 
 use shute::{Instance, PowerPreference};
 
 async fn check() {
     let instance = Instance::new();
-    println!("All devices:");
-    for device in instance.devices() {
-        if let Ok(device) = device {
-            println!("{:#?}", device.info());
-        }
+    // Listing adapters does not create any devices, so it is cheap.
+    println!("All adapters:");
+    for adapter in instance.adapters() {
+        println!("{:#?}", adapter.info());
+        println!("Supported features: {}", adapter.features());
     }
     println!("=====");
     let performance_device = instance
-        .autoselect(PowerPreference::HighPerformance, shute::LimitType::Highest)
+        .autoselect(
+            PowerPreference::HighPerformance,
+            shute::LimitType::Highest,
+            shute::Features::empty(),
+        )
         .await
         .unwrap();
     println!(
@@ -23,7 +27,11 @@ async fn check() {
     println!("Limits: {:#?}", performance_device.limits());
     println!("=====");
     let lowpower_device = instance
-        .autoselect(PowerPreference::LowPower, shute::LimitType::Highest)
+        .autoselect(
+            PowerPreference::LowPower,
+            shute::LimitType::Highest,
+            shute::Features::empty(),
+        )
         .await
         .unwrap();
     println!(
@@ -33,7 +41,11 @@ async fn check() {
     println!("Limits: {:#?}", lowpower_device.limits());
     println!("=====");
     let no_preference_device = instance
-        .autoselect(PowerPreference::None, shute::LimitType::Highest)
+        .autoselect(
+            PowerPreference::None,
+            shute::LimitType::Highest,
+            shute::Features::empty(),
+        )
         .await
         .unwrap();
     println!(
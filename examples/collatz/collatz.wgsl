@@ -1,5 +1,7 @@
 @group(0) @binding(0) var<storage, read> input: array<u32>;
 @group(0) @binding(1) var<storage, read_write> output: array<u32>;
+// Bound automatically by `Device::execute_over`.
+@group(0) @binding(2) var<uniform> problem_size: vec3<u32>;
 
 fn collatz(n_init: u32) -> u32 {
     var n = n_init;
@@ -21,7 +23,10 @@ fn collatz(n_init: u32) -> u32 {
     return i;
 }
 
-@compute @workgroup_size(1)
+@compute @workgroup_size(64)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if (global_id.x >= problem_size.x) {
+        return;
+    }
     output[global_id.x] = collatz(input[global_id.x]);
 }
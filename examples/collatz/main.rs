@@ -6,35 +6,44 @@
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use shute::{BufferInit, BufferType, Instance, PowerPreference};
 
-fn collatz(data: &Vec<u32>) -> Vec<u32> {
+fn collatz(data: &[u32]) -> Vec<u32> {
     let instance = Instance::new();
-    let device = pollster::block_on(
-        instance.autoselect(PowerPreference::HighPerformance, shute::LimitType::Highest),
-    )
+    let device = pollster::block_on(instance.autoselect(
+        PowerPreference::HighPerformance,
+        shute::LimitType::Highest,
+        shute::Features::empty(),
+    ))
     .expect("Failed to select device");
-    let mut input_buffer = device.create_buffer(
-        Some("input"),
-        BufferType::StorageBuffer {
-            output: false,
-            read_only: true,
-        },
-        BufferInit::WithData(data),
-    );
-    let mut output_buffer = device.create_buffer(
-        Some("output"),
-        BufferType::StorageBuffer {
-            output: true,
-            read_only: false,
-        },
-        BufferInit::<u32>::WithSize(data.len()),
-    );
-    let shader = device.create_shader_module(include_str!("collatz.wgsl"), "main");
-    let groups = vec![vec![&mut input_buffer, &mut output_buffer]];
-    device.execute(&groups, shader, [data.len() as u32]);
-    let mut output = vec![0; data.len()];
-    pollster::block_on(output_buffer.read(&mut output))
-        .expect("Failed to fetch data from output buffer");
-    output
+    let mut input_buffer = device
+        .create_buffer(
+            Some("input"),
+            BufferType::StorageBuffer {
+                output: false,
+                read_only: true,
+            },
+            BufferInit::WithData(data),
+        )
+        .expect("Failed to create buffer");
+    let mut output_buffer = device
+        .create_buffer(
+            Some("output"),
+            BufferType::StorageBuffer {
+                output: true,
+                read_only: false,
+            },
+            BufferInit::<u32>::WithSize(data.len()),
+        )
+        .expect("Failed to create buffer");
+    let shader = device
+        .create_shader_module(include_str!("collatz.wgsl"), "main")
+        .expect("Failed to compile shader module");
+    // The problem size uniform is bound by the device, so only the buffers are given here.
+    let groups = vec![vec![&mut *input_buffer, &mut *output_buffer]];
+    device
+        .execute_over(&groups, &shader, [data.len() as u32])
+        .expect("Failed to execute shader");
+    pollster::block_on(output_buffer.read_to_vec())
+        .expect("Failed to fetch data from output buffer")
 }
 
 fn generate_data(n: usize) -> Vec<u32> {
@@ -1,7 +1,10 @@
 //! A naive implementation of reduction, roughly matching the implementation of Reduction #1
 //! from the CUDA presentation (?) [Optimizing Parallel Reduction in CUDA](https://developer.download.nvidia.com/assets/cuda/files/reduction.pdf)
 
-use shute::{BufferType, Instance, LimitType, PowerPreference};
+use shute::{
+    Bindings, BufferType, Features, Instance, LINEAR_INDEX_WGSL, LimitType, PingPong,
+    PowerPreference,
+};
 
 fn generate_random_data(n: usize) -> Vec<i32> {
     use rand::Rng;
@@ -9,65 +12,84 @@ fn generate_random_data(n: usize) -> Vec<i32> {
     (0..n).map(|_| rng.gen_range(-10..10)).collect()
 }
 
-fn compute(data: &Vec<i32>) -> i32 {
+fn compute(data: &[i32]) -> i32 {
     let instance = Instance::new();
-    let device = pollster::block_on(
-        instance.autoselect(PowerPreference::HighPerformance, LimitType::Highest),
-    )
+    let device = pollster::block_on(instance.autoselect(
+        PowerPreference::HighPerformance,
+        LimitType::Highest,
+        Features::empty(),
+    ))
     .unwrap();
 
     // We are setting both the "input" and "output" buffers to be mutable output storage buffers
     // because we are going to continuously swap them every dispatch.
-    let mut buffer_a = device.create_buffer(
-        Some("input"),
-        BufferType::StorageBuffer {
-            output: true,
-            read_only: false,
-        },
-        shute::BufferInit::WithData(data),
-    );
-    let mut buffer_b = device.create_buffer(
-        Some("output"),
-        BufferType::StorageBuffer {
-            output: true,
-            read_only: false,
-        },
-        shute::BufferInit::<i32>::WithSize(data.len().div_ceil(128)),
-    );
-    let mut buffer_n = device.create_buffer(
-        Some("n"),
-        BufferType::UniformBuffer,
-        shute::BufferInit::WithData(data.len() as u32),
-    );
+    let buffer_a = device
+        .create_buffer(
+            Some("input"),
+            BufferType::StorageBuffer {
+                output: true,
+                read_only: false,
+            },
+            shute::BufferInit::WithData(data),
+        )
+        .expect("Failed to create buffer");
+    let buffer_b = device
+        .create_buffer(
+            Some("output"),
+            BufferType::StorageBuffer {
+                output: true,
+                read_only: false,
+            },
+            shute::BufferInit::<i32>::WithSize(data.len().div_ceil(128)),
+        )
+        .expect("Failed to create buffer");
+    let buffer_n = device
+        .create_buffer(
+            Some("n"),
+            BufferType::UniformBuffer,
+            shute::BufferInit::WithData(&[data.len() as u32]),
+        )
+        .expect("Failed to create buffer");
+    // The shader module is only compiled once, letting the device reuse the compute pipeline
+    // it builds for the module on every dispatch.
+    // The first passes need more workgroups than fit in a single dimension. As the shader gets
+    // its workgroup index through `linear_workgroup_index`, the device folds those dispatches
+    // into a 2D grid.
+    let source = format!("{}\n{}", include_str!("reduction.wgsl"), LINEAR_INDEX_WGSL);
+    let shader = device
+        .create_shader_module(&source, "main")
+        .expect("Failed to compile shader module");
+    // Every pass reads from the front buffer and writes to the back buffer. Swapping them
+    // afterwards makes the partial sums of the pass the input of the next one.
+    let mut buffers = PingPong::new(buffer_a, buffer_b);
     let mut remaining = data.len();
-    let mut count = 0;
     while remaining > 1 {
-        let groups = vec![vec![&mut buffer_a, &mut buffer_b, &mut buffer_n]];
-        let shader = device.create_shader_module(include_str!("reduction.wgsl"), "main");
+        let bindings = Bindings::new()
+            .bind_ping_pong("input", "output", &buffers)
+            .bind("n", &buffer_n);
         remaining = remaining.div_ceil(128);
-        device.execute(&groups, shader, [remaining as u32]);
+        device
+            .execute(&bindings, &shader, [remaining as u32])
+            .expect("Failed to execute shader");
+        buffers.swap();
         if remaining > 1 {
-            buffer_n.write(&(remaining as u32));
-            std::mem::swap(&mut buffer_a, &mut buffer_b);
+            buffer_n
+                .write(&[remaining as u32])
+                .expect("Failed to write to buffer");
         }
-        count += 1;
     }
+    // Only the first element holds the result, so the rest of the buffer is not copied back.
     let mut output: Vec<i32> = vec![0];
-    pollster::block_on(if count % 2 == 0 {
-        buffer_b.read(&mut output)
-    } else {
-        buffer_a.read(&mut output)
-    })
-    .expect("Failed to fetch data from output buffer");
+    pollster::block_on(buffers.front().read_range(0..1, &mut output))
+        .expect("Failed to fetch data from output buffer");
     output[0]
 }
 
 fn main() {
     use std::time::Instant;
 
-    // 128 needs to be subtracted as my GPU (GTX 1060) has a workgroup size limit of 65535.
-    // 65535 * 128 = 8388480 = (2 << 22) - 128
-    let data = generate_random_data((2 << 22) - 128);
+    // This needs more than the 65535 workgroups per dimension most GPUs support.
+    let data = generate_random_data((2 << 23) + 12345);
     let now = Instant::now();
     let expected: i32 = data.iter().copied().reduce(|acc, e| acc + e).unwrap_or(0);
     println!("CPU Elapsed time: {:?}", now.elapsed());
@@ -7,10 +7,14 @@ var<workgroup> shared_data: array<i32, 128>;
 @compute @workgroup_size(128)
 fn main(
     @builtin(local_invocation_id) local_id: vec3<u32>,
-    @builtin(workgroup_id) wg_id: vec3<u32>
+    @builtin(workgroup_id) wg_id: vec3<u32>,
+    @builtin(num_workgroups) num_wg: vec3<u32>
 ) {
+    // Large dispatches are folded into a 2D grid, so the workgroup index has to be recovered
+    // from all dimensions.
+    let group = linear_workgroup_index(wg_id, num_wg);
     let t_id = local_id.x;
-    let i = wg_id.x * 128 + local_id.x;
+    let i = group * 128 + local_id.x;
     if (i < n) {
         shared_data[t_id] = input[i];
     }
@@ -23,7 +27,8 @@ fn main(
         workgroupBarrier();
     }
 
-    if (t_id == 0u) {
-        output[wg_id.x] = shared_data[0];
+    // Folding may add a few workgroups past the end of the input, which must not write.
+    if (t_id == 0u && group * 128 < n) {
+        output[group] = shared_data[0];
     }
 }
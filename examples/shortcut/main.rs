@@ -5,7 +5,7 @@
 //! CPU reference function is derived from [Chapter 2, V2](https://ppc.cs.aalto.fi/ch2/v2/).
 
 use rand::Rng;
-use shute::{Buffer, Instance, LimitType, PowerPreference};
+use shute::{Features, Instance, LimitType, PowerPreference, RawBuffer};
 
 fn generate_data(dim: usize) -> Vec<f32> {
     let mut rng = rand::thread_rng();
@@ -18,36 +18,51 @@ fn generate_data(dim: usize) -> Vec<f32> {
 
 fn compute(data: &mut Vec<f32>, dim: u32) {
     let instance = Instance::new();
-    let device = pollster::block_on(
-        instance.autoselect(PowerPreference::HighPerformance, LimitType::Highest),
-    )
+    let device = pollster::block_on(instance.autoselect(
+        PowerPreference::HighPerformance,
+        LimitType::Highest,
+        Features::empty(),
+    ))
     .unwrap();
 
-    let mut input_buffer = device.create_buffer(
-        Some("input"),
-        shute::BufferType::StorageBuffer {
-            output: true,
-            read_only: true,
-        },
-        shute::BufferInit::WithData(&data),
-    );
-    let mut output_buffer = device.create_buffer(
-        Some("output"),
-        shute::BufferType::StorageBuffer {
-            output: true,
-            read_only: false,
-        },
-        shute::BufferInit::<f32>::WithSize(data.len()),
-    );
-    let mut dim_buffer = device.create_buffer(
-        Some("dim"),
-        shute::BufferType::UniformBuffer,
-        shute::BufferInit::WithData(dim),
-    );
-    let groups: Vec<Vec<&mut Buffer>> =
-        vec![vec![&mut input_buffer, &mut output_buffer, &mut dim_buffer]];
-    let shader = device.create_shader_module(include_str!("shortcut.wgsl"), "main");
-    device.execute(&groups, shader, [dim, dim]);
+    let mut input_buffer = device
+        .create_buffer(
+            Some("input"),
+            shute::BufferType::StorageBuffer {
+                output: true,
+                read_only: true,
+            },
+            shute::BufferInit::WithData(data),
+        )
+        .expect("Failed to create buffer");
+    let mut output_buffer = device
+        .create_buffer(
+            Some("output"),
+            shute::BufferType::StorageBuffer {
+                output: true,
+                read_only: false,
+            },
+            shute::BufferInit::<f32>::WithSize(data.len()),
+        )
+        .expect("Failed to create buffer");
+    let mut dim_buffer = device
+        .create_buffer(
+            Some("dim"),
+            shute::BufferType::UniformBuffer,
+            shute::BufferInit::WithData(&[dim]),
+        )
+        .expect("Failed to create buffer");
+    let groups: Vec<Vec<&mut RawBuffer>> = vec![vec![
+        &mut *input_buffer,
+        &mut *output_buffer,
+        &mut *dim_buffer,
+    ]];
+    let shader = device
+        .create_shader_module(include_str!("shortcut.wgsl"), "main")
+        .expect("Failed to compile shader module");
+    device
+        .execute(&groups, &shader, [dim, dim])
+        .expect("Failed to execute shader");
     pollster::block_on(output_buffer.read(data)).expect("Failed to read output buffer");
 }
 
@@ -5,7 +5,7 @@
 //! CPU reference function is derived from [Chapter 2, V2](https://ppc.cs.aalto.fi/ch2/v2/).
 
 use rand::Rng;
-use shute::{Buffer, BufferInit, BufferType, Instance, LimitType, PowerPreference};
+use shute::{BufferInit, BufferType, Features, Instance, LimitType, PowerPreference, RawBuffer};
 
 fn generate_data(dim: usize) -> Vec<f32> {
     let mut rng = rand::thread_rng();
@@ -20,34 +20,51 @@ async fn compute(data: &mut Vec<f32>, dim: u32) {
     // let now = Instant::now();
     let instance = Instance::new();
     let device = instance
-        .autoselect(PowerPreference::HighPerformance, LimitType::Highest)
+        .autoselect(
+            PowerPreference::HighPerformance,
+            LimitType::Highest,
+            Features::empty(),
+        )
         .await
         .unwrap();
-    let mut input_buffer = device.create_buffer(
-        Some("input"),
-        BufferType::StorageBuffer {
-            output: true,
-            read_only: true,
-        },
-        BufferInit::WithData(&data),
-    );
-    let mut output_buffer = device.create_buffer(
-        Some("output"),
-        BufferType::StorageBuffer {
-            output: true,
-            read_only: false,
-        },
-        BufferInit::<f32>::WithSize(data.len()),
-    );
-    let mut dim_buffer = device.create_buffer(
-        Some("dim"),
-        BufferType::UniformBuffer,
-        BufferInit::WithData(dim),
-    );
-    let groups: Vec<Vec<&mut Buffer>> =
-        vec![vec![&mut input_buffer, &mut output_buffer, &mut dim_buffer]];
-    let shader = device.create_shader_module(include_str!("shortcut.wgsl"), "main");
-    device.execute(&groups, shader, [dim.div_ceil(16), dim.div_ceil(16)]);
+    let mut input_buffer = device
+        .create_buffer(
+            Some("input"),
+            BufferType::StorageBuffer {
+                output: true,
+                read_only: true,
+            },
+            BufferInit::WithData(data),
+        )
+        .expect("Failed to create buffer");
+    let mut output_buffer = device
+        .create_buffer(
+            Some("output"),
+            BufferType::StorageBuffer {
+                output: true,
+                read_only: false,
+            },
+            BufferInit::<f32>::WithSize(data.len()),
+        )
+        .expect("Failed to create buffer");
+    let mut dim_buffer = device
+        .create_buffer(
+            Some("dim"),
+            BufferType::UniformBuffer,
+            BufferInit::WithData(&[dim]),
+        )
+        .expect("Failed to create buffer");
+    let groups: Vec<Vec<&mut RawBuffer>> = vec![vec![
+        &mut *input_buffer,
+        &mut *output_buffer,
+        &mut *dim_buffer,
+    ]];
+    let shader = device
+        .create_shader_module(include_str!("shortcut.wgsl"), "main")
+        .expect("Failed to compile shader module");
+    device
+        .execute_over(&groups, &shader, [dim, dim])
+        .expect("Failed to execute shader");
     output_buffer
         .read(data)
         .await
@@ -1,31 +1,44 @@
-use shute::{Instance, PowerPreference};
+use shute::{Bindings, Instance, PowerPreference};
 
 fn compute(data: &mut Vec<u32>) {
     let instance = Instance::new();
-    let device = pollster::block_on(
-        instance.autoselect(PowerPreference::HighPerformance, shute::LimitType::Highest),
-    )
+    let device = pollster::block_on(instance.autoselect(
+        PowerPreference::HighPerformance,
+        shute::LimitType::Highest,
+        shute::Features::empty(),
+    ))
     .unwrap();
-    let shader = device.create_shader_module(include_str!("square.wgsl"), "main");
-    let mut input_buffer = device.create_buffer(
-        Some("input"),
-        shute::BufferType::StorageBuffer {
-            output: false,
-            read_only: true,
-        },
-        shute::BufferInit::WithData(&data),
-    );
+    let shader = device
+        .create_shader_module(include_str!("square.wgsl"), "main")
+        .expect("Failed to compile shader module");
+    let input_buffer = device
+        .create_buffer(
+            Some("input"),
+            shute::BufferType::StorageBuffer {
+                output: false,
+                read_only: true,
+            },
+            shute::BufferInit::WithData(data),
+        )
+        .expect("Failed to create buffer");
     let size = data.len();
-    let mut output_buffer = device.create_buffer(
-        Some("output"),
-        shute::BufferType::StorageBuffer {
-            output: true,
-            read_only: false,
-        },
-        shute::BufferInit::<u32>::WithSize(size),
-    );
-    let groups = vec![vec![&mut input_buffer, &mut output_buffer]];
-    device.execute(&groups, shader, [size as u32]);
+    let output_buffer = device
+        .create_buffer(
+            Some("output"),
+            shute::BufferType::StorageBuffer {
+                output: true,
+                read_only: false,
+            },
+            shute::BufferInit::<u32>::WithSize(size),
+        )
+        .expect("Failed to create buffer");
+    // Buffers are bound to the global variables of the same name in the shader.
+    let bindings = Bindings::new()
+        .bind("input", &input_buffer)
+        .bind("output", &output_buffer);
+    device
+        .execute(&bindings, &shader, [size as u32])
+        .expect("Failed to execute shader");
     pollster::block_on(output_buffer.read(data)).expect("Failed to fetch data from output buffer");
 }
 
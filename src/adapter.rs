@@ -0,0 +1,49 @@
+use crate::{
+    device::{Device, DeviceError, LimitType},
+    types::{Backend, DeviceInfo, Features, Limits},
+};
+
+/// A physical device (like a GPU) that has not been opened yet. Obtain adapters by using
+/// `Instance::adapters`.
+///
+/// An adapter only describes a device, so listing adapters is cheap and does not allocate
+/// anything on the GPUs. Use [Adapter::request_device] to create a [Device] from it.
+#[derive(Debug)]
+pub struct Adapter {
+    adapter: wgpu::Adapter,
+}
+
+impl Adapter {
+    /// Used to wrap a wgpu adapter. Use `Instance::adapters` instead.
+    pub(crate) fn new(adapter: wgpu::Adapter) -> Self {
+        Self { adapter }
+    }
+    /// Gets the adapter's information.
+    pub fn info(&self) -> DeviceInfo {
+        self.adapter.get_info()
+    }
+    /// Gets the backend (like Vulkan, Metal, or DX12) the adapter is accessed through.
+    pub fn backend(&self) -> Backend {
+        self.adapter.get_info().backend
+    }
+    /// Gets the highest limits the adapter supports, which are the limits a device gets
+    /// with `LimitType::Highest`.
+    pub fn limits(&self) -> Limits {
+        self.adapter.limits().into()
+    }
+    /// Gets all features the adapter supports.
+    pub fn features(&self) -> Features {
+        self.adapter.features().into()
+    }
+    /// Creates a device from the adapter with the given limits and features.
+    /// See [LimitType] for more information about the limits.
+    ///
+    /// Returns an error if the adapter does not support the limits or features.
+    pub async fn request_device(
+        self,
+        limit_type: LimitType,
+        features: Features,
+    ) -> Result<Device, DeviceError> {
+        Device::new(self.adapter, limit_type, features).await
+    }
+}
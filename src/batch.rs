@@ -0,0 +1,458 @@
+use std::{
+    marker::PhantomData,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use encase::{
+    ShaderSize, ShaderType,
+    internal::{CreateFrom, ReadFrom, WriteInto},
+};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    Device, Features,
+    bindings::BufferBindings,
+    buffer::{
+        Buffer, BufferError, RawBuffer, element_stride, encode_repeated, read_staging,
+        read_staging_with,
+    },
+    device::{Dimensions, ExecuteError},
+    shader::ShaderModule,
+    staging::StagingBuffer,
+};
+
+/// A batch of commands that is submitted to the GPU all at once.
+///
+/// Create a batch using `Device::batch`. Commands are recorded in the order their methods are
+/// called and nothing is sent to the GPU until [CommandBatch::submit] is called. Batching
+/// multi-pass algorithms (like a padding pass followed by the actual computation) avoids
+/// the overhead of submitting every pass on its own.
+pub struct CommandBatch<'a> {
+    device: &'a Device,
+    encoder: wgpu::CommandEncoder,
+    submitted: Arc<AtomicBool>,
+    push_constants: Vec<u8>,
+    /// The query sets holding the timestamps of the timed dispatches, if dispatches are timed.
+    timestamps: Option<Vec<wgpu::QuerySet>>,
+}
+
+impl<'a> CommandBatch<'a> {
+    /// Used to create a new command batch. Use `Device::batch` instead.
+    pub(crate) fn new(device: &'a Device) -> Self {
+        Self {
+            device,
+            encoder: device
+                .device()
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None }),
+            submitted: Arc::new(AtomicBool::new(false)),
+            push_constants: Vec::new(),
+            timestamps: None,
+        }
+    }
+    /// Sets the push constants used by the dispatches recorded after this call.
+    ///
+    /// The data must be laid out the way the shader expects it (for plain-old-data types,
+    /// `bytemuck::bytes_of` does this), and must be exactly as large as the push constants of
+    /// every shader dispatched with it, as given by `ShaderModule::push_constant_size`.
+    /// Using push constants requires the `push_constants` feature.
+    pub fn set_push_constants(&mut self, data: &[u8]) -> &mut Self {
+        self.push_constants = data.to_vec();
+        self
+    }
+    /// Starts timing the dispatches recorded after this call on the GPU. The durations are
+    /// read using [CommandBatch::readback_timings].
+    ///
+    /// Returns an error if the device does not have the `timestamp_queries` feature.
+    pub fn time_dispatches(&mut self) -> Result<&mut Self, ExecuteError> {
+        if !self.device.features().timestamp_queries {
+            return Err(ExecuteError::MissingFeatures(Features {
+                timestamp_queries: true,
+                ..Features::empty()
+            }));
+        }
+        self.timestamps.get_or_insert_default();
+        Ok(self)
+    }
+    /// Records a copy of the timestamps of the dispatches timed so far, so that their
+    /// durations can be read once the batch has been submitted. Dispatches recorded after
+    /// this call are timed in the next readback.
+    pub fn readback_timings(&mut self) -> Timings<'a> {
+        let query_sets = self
+            .timestamps
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default();
+        let len = query_sets.len();
+        let staging = (len > 0).then(|| {
+            // Query sets are resolved to offsets aligned to `QUERY_RESOLVE_BUFFER_ALIGNMENT`,
+            // so every pair of timestamps starts a new chunk of the buffer.
+            let size = len as u64 * wgpu::QUERY_RESOLVE_BUFFER_ALIGNMENT;
+            let resolved = self.device.device().create_buffer(&wgpu::BufferDescriptor {
+                label: Some("shute timestamps"),
+                size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            for (idx, query_set) in query_sets.iter().enumerate() {
+                self.encoder.resolve_query_set(
+                    query_set,
+                    0..2,
+                    &resolved,
+                    idx as u64 * wgpu::QUERY_RESOLVE_BUFFER_ALIGNMENT,
+                );
+            }
+            let mut staging = self.device.staging().acquire(self.device.device(), size);
+            staging.set_idle(false);
+            self.encoder
+                .copy_buffer_to_buffer(&resolved, 0, &staging, 0, size);
+            staging
+        });
+        Timings {
+            device: self.device,
+            staging,
+            len,
+            submitted: self.submitted.clone(),
+        }
+    }
+    /// Records the execution of a compute shader with the given buffers and dispatch dimensions.
+    ///
+    /// This works exactly like `Device::execute`, except that the dispatch is only submitted
+    /// along with the rest of the batch. Errors that can only be detected once the commands
+    /// are submitted are returned by [CommandBatch::submit].
+    pub fn execute<B, const N: usize>(
+        &mut self,
+        buffers: &B,
+        shader_module: &ShaderModule,
+        dispatch_dimensions: [u32; N],
+    ) -> Result<&mut Self, ExecuteError>
+    where
+        B: BufferBindings + ?Sized,
+        [u32; N]: Dimensions,
+    {
+        self.dispatch(
+            buffers,
+            shader_module,
+            [
+                dispatch_dimensions.x(),
+                dispatch_dimensions.y(),
+                dispatch_dimensions.z(),
+            ],
+            None,
+        )
+    }
+    /// Records the execution of a compute shader over a problem of the given size.
+    ///
+    /// This works exactly like `Device::execute_over`, except that the dispatch is only
+    /// submitted along with the rest of the batch.
+    pub fn execute_over<B, const N: usize>(
+        &mut self,
+        buffers: &B,
+        shader_module: &ShaderModule,
+        problem_dimensions: [u32; N],
+    ) -> Result<&mut Self, ExecuteError>
+    where
+        B: BufferBindings + ?Sized,
+        [u32; N]: Dimensions,
+    {
+        let problem_size = [
+            problem_dimensions.x(),
+            problem_dimensions.y(),
+            problem_dimensions.z(),
+        ];
+        let workgroup_size = shader_module.workgroup_size();
+        let dispatch_dimensions =
+            std::array::from_fn(|idx| problem_size[idx].div_ceil(workgroup_size[idx].max(1)));
+        self.dispatch(
+            buffers,
+            shader_module,
+            dispatch_dimensions,
+            Some(problem_size),
+        )
+    }
+    /// Records a dispatch. If `problem_size` is given and the shader declares a problem size
+    /// uniform, the uniform is bound and set to the problem size right before the dispatch.
+    fn dispatch<B>(
+        &mut self,
+        buffers: &B,
+        shader_module: &ShaderModule,
+        dispatch_dimensions: [u32; 3],
+        problem_size: Option<[u32; 3]>,
+    ) -> Result<&mut Self, ExecuteError>
+    where
+        B: BufferBindings + ?Sized,
+    {
+        let dispatch_dimensions = self
+            .device
+            .validate_dispatch(dispatch_dimensions, shader_module.uses_linear_index())?;
+        let problem_size = problem_size.and_then(|problem_size| {
+            Some((
+                problem_size,
+                shader_module.problem_size_buffer(self.device)?,
+            ))
+        });
+        let buffers = buffers.resolve(shader_module, problem_size.map(|(_, buffer)| buffer))?;
+        let push_constant_size = shader_module.push_constant_size();
+        if push_constant_size > 0 && self.push_constants.len() != push_constant_size as usize {
+            return Err(ExecuteError::PushConstantSizeMismatch {
+                expected: push_constant_size,
+                found: self.push_constants.len(),
+            });
+        }
+        let (pipeline, bind_groups) = self.device.prepare_dispatch(&buffers, shader_module)?;
+        if let Some(([x, y, z], buffer)) = problem_size {
+            // Every dispatch of the module shares its problem size uniform (which keeps its bind
+            // groups cached), so the size is copied into it in the same batch, right before the
+            // dispatch. Writing it through the queue instead would take effect before the batch
+            // is submitted, so all dispatches in the batch would see the last size written.
+            let source =
+                self.device
+                    .device()
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("shute problem size source"),
+                        contents: bytemuck::cast_slice(&[x, y, z, 0]),
+                        usage: wgpu::BufferUsages::COPY_SRC,
+                    });
+            self.encoder
+                .copy_buffer_to_buffer(&source, 0, buffer.buffer(), 0, buffer.size());
+        }
+        let query_set = self.timestamps.as_mut().map(|timestamps| {
+            timestamps.push(
+                self.device
+                    .device()
+                    .create_query_set(&wgpu::QuerySetDescriptor {
+                        label: Some("shute dispatch timestamps"),
+                        ty: wgpu::QueryType::Timestamp,
+                        count: 2,
+                    }),
+            );
+            &timestamps[timestamps.len() - 1]
+        });
+        {
+            let mut compute_pass = self
+                .encoder
+                .begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: None,
+                    timestamp_writes: query_set.map(|query_set| wgpu::ComputePassTimestampWrites {
+                        query_set,
+                        beginning_of_pass_write_index: Some(0),
+                        end_of_pass_write_index: Some(1),
+                    }),
+                });
+            compute_pass.set_pipeline(&pipeline.pipeline);
+            if push_constant_size > 0 {
+                compute_pass.set_push_constants(0, &self.push_constants);
+            }
+            for (idx, bind_group) in bind_groups.iter().enumerate() {
+                compute_pass.set_bind_group(idx as u32, bind_group, &[]);
+            }
+            compute_pass.dispatch_workgroups(
+                dispatch_dimensions[0],
+                dispatch_dimensions[1],
+                dispatch_dimensions[2],
+            );
+        }
+        Ok(self)
+    }
+    /// Records a copy of `size` bytes from `source` (starting at `source_offset`) to
+    /// `destination` (starting at `destination_offset`).
+    ///
+    /// Returns an error if either range does not lie within its buffer, if an offset or the
+    /// size is not a multiple of `wgpu::COPY_BUFFER_ALIGNMENT` bytes, or if both buffers
+    /// are the same.
+    pub fn copy_buffer(
+        &mut self,
+        source: &RawBuffer,
+        source_offset: u64,
+        destination: &RawBuffer,
+        destination_offset: u64,
+        size: u64,
+    ) -> Result<&mut Self, BufferError> {
+        if source.id() == destination.id() {
+            return Err(BufferError::CopyToSelf);
+        }
+        source.check_range(source_offset, size)?;
+        destination.check_range(destination_offset, size)?;
+        self.encoder.copy_buffer_to_buffer(
+            source.buffer(),
+            source_offset,
+            destination.buffer(),
+            destination_offset,
+            size,
+        );
+        Ok(self)
+    }
+    /// Records the clearing of a buffer, setting all of its bytes to zero.
+    ///
+    /// Returns an error if the size of the buffer is not a multiple of
+    /// `wgpu::COPY_BUFFER_ALIGNMENT` bytes.
+    pub fn clear_buffer(&mut self, buffer: &RawBuffer) -> Result<&mut Self, BufferError> {
+        buffer.check_range(0, buffer.size())?;
+        self.encoder.clear_buffer(buffer.buffer(), 0, None);
+        Ok(self)
+    }
+    /// Records setting every element of the buffer to the given value.
+    ///
+    /// The value is uploaded to a temporary buffer right away, and copied into the buffer
+    /// when the batch is executed. Filling a buffer with zeros does not need the temporary
+    /// buffer.
+    pub fn fill<T>(&mut self, buffer: &Buffer<T>, value: &T) -> Result<&mut Self, BufferError>
+    where
+        T: ShaderType + ShaderSize + WriteInto,
+    {
+        let bytes = encode_repeated(buffer.buffer_type(), value, buffer.len())?;
+        if bytes.iter().all(|&byte| byte == 0) {
+            return self.clear_buffer(buffer);
+        }
+        let source = self
+            .device
+            .device()
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("shute fill buffer"),
+                contents: &bytes,
+                usage: wgpu::BufferUsages::COPY_SRC,
+            });
+        self.encoder
+            .copy_buffer_to_buffer(&source, 0, buffer.buffer(), 0, bytes.len() as u64);
+        Ok(self)
+    }
+    /// Records a copy of the buffer's current contents so that they can be read
+    /// once the batch has been submitted.
+    ///
+    /// Every readback gets its own staging buffer, so several buffers can be read back
+    /// from a single batch.
+    pub fn readback<T>(&mut self, buffer: &Buffer<T>) -> Result<Readback<'a, T>, BufferError>
+    where
+        T: ShaderType + ShaderSize,
+    {
+        if !buffer.output() {
+            return Err(BufferError::NotOutputBuffer);
+        }
+        let size = buffer.size();
+        let mut staging = self.device.staging().acquire(self.device.device(), size);
+        // The staging buffer only returns to the pool once the copy has been read, as the
+        // batch may still be submitted after the readback is dropped.
+        staging.set_idle(false);
+        self.encoder
+            .copy_buffer_to_buffer(buffer.buffer(), 0, &staging, 0, size);
+        Ok(Readback {
+            device: self.device,
+            staging,
+            stride: element_stride::<T>(buffer.buffer_type()),
+            len: buffer.len(),
+            submitted: self.submitted.clone(),
+            _marker: PhantomData,
+        })
+    }
+    /// Submits all recorded commands to the GPU.
+    ///
+    /// Returns an error if wgpu reports a validation error for any of the recorded commands,
+    /// in which case none of them are executed and the readbacks of the batch cannot be read.
+    /// On WebAssembly, validation errors from wgpu go to its uncaptured error handler instead.
+    pub fn submit(self) -> Result<(), ExecuteError> {
+        let device = self.device;
+        let submitted = self.submitted.clone();
+        device.capture_errors(|| self.submit_commands())?;
+        submitted.store(true, Ordering::Release);
+        Ok(())
+    }
+    /// Submits all recorded commands to the GPU without capturing validation errors.
+    /// Only use this for commands that were fully validated while they were recorded.
+    pub(crate) fn submit_unchecked(self) {
+        let submitted = self.submitted.clone();
+        self.submit_commands();
+        submitted.store(true, Ordering::Release);
+    }
+    fn submit_commands(self) {
+        self.device.queue().submit(Some(self.encoder.finish()));
+    }
+}
+
+/// A copy of a buffer's contents, recorded using [CommandBatch::readback].
+pub struct Readback<'a, T> {
+    device: &'a Device,
+    staging: StagingBuffer<'a>,
+    stride: u64,
+    len: usize,
+    submitted: Arc<AtomicBool>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Readback<'_, T>
+where
+    T: ShaderType + ShaderSize + ReadFrom + CreateFrom,
+{
+    /// Read the copied elements into the output vector, which must have the same length as
+    /// the buffer. The batch this readback was recorded in must have been submitted.
+    pub async fn read(mut self, output: &mut Vec<T>) -> Result<(), BufferError> {
+        if !self.submitted.load(Ordering::Acquire) {
+            return Err(BufferError::NotSubmitted);
+        }
+        if output.len() != self.len {
+            return Err(BufferError::OutputSizeMismatch {
+                expected: self.len as u64 * self.stride,
+                found: output.len() as u64 * self.stride,
+            });
+        }
+        self.read_elements(output).await
+    }
+    /// Read the copied elements into a newly allocated vector. The batch this readback was
+    /// recorded in must have been submitted.
+    pub async fn read_to_vec(mut self) -> Result<Vec<T>, BufferError> {
+        if !self.submitted.load(Ordering::Acquire) {
+            return Err(BufferError::NotSubmitted);
+        }
+        let mut output = Vec::with_capacity(self.len);
+        self.read_elements(&mut output).await?;
+        Ok(output)
+    }
+    /// Read the copied elements, replacing the contents of the output vector.
+    ///
+    /// Only `len` elements are read, so the padding of a buffer created without any elements
+    /// is not returned as an element.
+    async fn read_elements(&mut self, output: &mut Vec<T>) -> Result<(), BufferError> {
+        if self.len == 0 {
+            output.clear();
+            return Ok(());
+        }
+        let size = self.len as u64 * self.stride;
+        read_staging(self.device, &mut self.staging, size, output).await
+    }
+}
+
+/// The durations of the dispatches timed in a batch, recorded using
+/// [CommandBatch::readback_timings].
+pub struct Timings<'a> {
+    device: &'a Device,
+    staging: Option<StagingBuffer<'a>>,
+    len: usize,
+    submitted: Arc<AtomicBool>,
+}
+
+impl Timings<'_> {
+    /// Read the time each timed dispatch took on the GPU, in the order they were recorded.
+    /// The batch these timings were recorded in must have been submitted.
+    pub async fn read(mut self) -> Result<Vec<Duration>, BufferError> {
+        if !self.submitted.load(Ordering::Acquire) {
+            return Err(BufferError::NotSubmitted);
+        }
+        let Some(staging) = self.staging.as_mut() else {
+            return Ok(Vec::new());
+        };
+        let period = self.device.queue().get_timestamp_period() as f64;
+        let size = self.len as u64 * wgpu::QUERY_RESOLVE_BUFFER_ALIGNMENT;
+        read_staging_with(self.device, staging, size, |bytes| {
+            bytes
+                .chunks_exact(wgpu::QUERY_RESOLVE_BUFFER_ALIGNMENT as usize)
+                .map(|chunk| {
+                    let [start, end]: [u64; 2] = bytemuck::pod_read_unaligned(&chunk[..16]);
+                    Duration::from_nanos((end.saturating_sub(start) as f64 * period) as u64)
+                })
+                .collect()
+        })
+        .await
+    }
+}
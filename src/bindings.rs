@@ -0,0 +1,190 @@
+use crate::{
+    buffer::RawBuffer,
+    device::ExecuteError,
+    ping_pong::PingPong,
+    shader::{AddressSpace, ShaderBinding, ShaderModule},
+};
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// A sealed trait for the ways buffers can be bound to a compute shader in `Device::execute`.
+///
+/// Buffers can either be bound positionally with a `Vec<Vec<&mut RawBuffer>>`, where the buffers
+/// in the `n`th inner vector are bound to the bindings of `@group(n)` in the order of their
+/// `@binding` indices, or by the names of the global variables in the shader using [Bindings].
+/// Typed buffers are bound positionally through the raw buffer they dereference to,
+/// as in `&mut *buffer`.
+pub trait BufferBindings: private::Sealed {
+    /// Matches the buffers to the bindings of a shader module, returning them in the order
+    /// of `ShaderModule::bindings`.
+    ///
+    /// If `problem_size` is given, it is bound to the problem size uniform of the shader
+    /// (see `Device::execute_over`), which must then not be bound explicitly.
+    #[doc(hidden)]
+    fn resolve<'b>(
+        &'b self,
+        shader_module: &ShaderModule,
+        problem_size: Option<&'b RawBuffer>,
+    ) -> Result<Vec<&'b RawBuffer>, ExecuteError>;
+}
+
+/// Gets the binding of the problem size uniform of a shader along with the buffer bound to it,
+/// if the problem size is bound implicitly.
+fn implicit_binding<'s, 'b>(
+    shader_module: &'s ShaderModule,
+    problem_size: Option<&'b RawBuffer>,
+) -> Option<(&'s ShaderBinding, &'b RawBuffer)> {
+    Some((shader_module.problem_size_binding()?, problem_size?))
+}
+
+impl private::Sealed for Vec<Vec<&mut RawBuffer>> {}
+
+impl BufferBindings for Vec<Vec<&mut RawBuffer>> {
+    fn resolve<'b>(
+        &'b self,
+        shader_module: &ShaderModule,
+        problem_size: Option<&'b RawBuffer>,
+    ) -> Result<Vec<&'b RawBuffer>, ExecuteError> {
+        let implicit = implicit_binding(shader_module, problem_size);
+        let is_implicit = |binding: &ShaderBinding| {
+            implicit.is_some_and(|(implicit, _)| {
+                (implicit.group, implicit.binding) == (binding.group, binding.binding)
+            })
+        };
+        if self.len() != shader_module.group_count() {
+            return Err(ExecuteError::GroupCountMismatch {
+                expected: shader_module.group_count(),
+                found: self.len(),
+            });
+        }
+        let mut bound = Vec::with_capacity(shader_module.bindings().len());
+        for (group, group_buffers) in self.iter().enumerate() {
+            let bindings: Vec<_> = shader_module.group_bindings(group as u32).collect();
+            let explicit = bindings
+                .iter()
+                .filter(|binding| !is_implicit(binding))
+                .count();
+            if explicit != group_buffers.len() {
+                return Err(ExecuteError::BindingCountMismatch {
+                    group: group as u32,
+                    expected: explicit,
+                    found: group_buffers.len(),
+                });
+            }
+            let mut group_buffers = group_buffers.iter();
+            for binding in bindings {
+                let buffer = match implicit {
+                    Some((_, buffer)) if is_implicit(binding) => buffer,
+                    _ => &**group_buffers.next().unwrap(),
+                };
+                validate_binding(binding, buffer)?;
+                bound.push(buffer);
+            }
+        }
+        Ok(bound)
+    }
+}
+
+/// Buffers bound to the global variables of a compute shader by name.
+///
+/// Every buffer binding declared in the shader must be bound exactly once, regardless of
+/// which group or binding index it has.
+#[derive(Default)]
+pub struct Bindings<'b> {
+    buffers: Vec<(String, &'b RawBuffer)>,
+}
+
+impl<'b> Bindings<'b> {
+    /// Creates an empty set of bindings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Binds a buffer to the global variable with the given name in the shader.
+    pub fn bind(mut self, name: &str, buffer: &'b RawBuffer) -> Self {
+        self.buffers.push((name.to_string(), buffer));
+        self
+    }
+    /// Binds the front buffer of a ping-pong pair to the global variable named `front` and
+    /// the back buffer to the global variable named `back`.
+    pub fn bind_ping_pong<B>(self, front: &str, back: &str, buffers: &'b PingPong<B>) -> Self
+    where
+        B: AsRef<RawBuffer>,
+    {
+        self.bind(front, buffers.front().as_ref())
+            .bind(back, buffers.back().as_ref())
+    }
+}
+
+impl private::Sealed for Bindings<'_> {}
+
+impl BufferBindings for Bindings<'_> {
+    fn resolve<'b>(
+        &'b self,
+        shader_module: &ShaderModule,
+        problem_size: Option<&'b RawBuffer>,
+    ) -> Result<Vec<&'b RawBuffer>, ExecuteError> {
+        let implicit = implicit_binding(shader_module, problem_size);
+        for (idx, (name, _)) in self.buffers.iter().enumerate() {
+            if self.buffers[..idx].iter().any(|(other, _)| other == name)
+                || implicit.is_some_and(|(binding, _)| binding.name.as_ref() == Some(name))
+            {
+                return Err(ExecuteError::DuplicateBinding(name.clone()));
+            }
+            if !shader_module
+                .bindings()
+                .iter()
+                .any(|binding| binding.name.as_ref() == Some(name))
+            {
+                return Err(ExecuteError::UnknownBinding(name.clone()));
+            }
+        }
+        shader_module
+            .bindings()
+            .iter()
+            .map(|binding| {
+                let buffer = self
+                    .buffers
+                    .iter()
+                    .find(|(name, _)| binding.name.as_ref() == Some(name))
+                    .map(|(_, buffer)| *buffer)
+                    .or_else(|| {
+                        implicit
+                            .filter(|(implicit, _)| implicit.name == binding.name)
+                            .map(|(_, buffer)| buffer)
+                    })
+                    .ok_or_else(|| ExecuteError::UnboundBinding(binding.clone()))?;
+                validate_binding(binding, buffer)?;
+                Ok(buffer)
+            })
+            .collect()
+    }
+}
+
+/// Checks that a buffer can be bound to the given binding of a shader.
+fn validate_binding(binding: &ShaderBinding, buffer: &RawBuffer) -> Result<(), ExecuteError> {
+    let compatible = match (binding.address_space, buffer.address_space()) {
+        (AddressSpace::Uniform, AddressSpace::Uniform) => true,
+        (
+            AddressSpace::Storage {
+                read_only: shader_read_only,
+            },
+            AddressSpace::Storage { read_only },
+        ) => shader_read_only || !read_only,
+        _ => false,
+    };
+    if !compatible {
+        return Err(ExecuteError::BindingTypeMismatch {
+            binding: binding.clone(),
+            found: buffer.address_space(),
+        });
+    }
+    if buffer.size() < binding.min_size {
+        return Err(ExecuteError::BufferTooSmall {
+            binding: binding.clone(),
+            size: buffer.size(),
+        });
+    }
+    Ok(())
+}
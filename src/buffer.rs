@@ -1,6 +1,12 @@
+use std::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut, Range},
+};
+
+use bytemuck::Pod;
 use encase::{
-    ShaderType, StorageBuffer, UniformBuffer,
-    internal::{ReadFrom, WriteInto},
+    CalculateSizeFor, ShaderSize, ShaderType, StorageBuffer, UniformBuffer,
+    internal::{CreateFrom, ReadFrom, WriteInto},
 };
 use thiserror::Error;
 use wgpu::{
@@ -8,7 +14,7 @@ use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
 };
 
-use crate::Device;
+use crate::{Device, cache::next_id, shader::AddressSpace, staging::StagingBuffer};
 
 /// Specifies buffer type.
 #[derive(Clone, Copy)]
@@ -25,70 +31,146 @@ pub enum BufferType {
     UniformBuffer,
 }
 
-/// A buffer for sharing data between the CPU and GPU.
+/// An untyped buffer for sharing data between the CPU and GPU.
 ///
-/// Create a buffer using the `Device::create_buffer` method.
-pub struct Buffer<'a> {
-    device: &'a Device,
+/// Create a raw buffer using the `Device::create_raw_buffer` method. Typed buffers created with
+/// `Device::create_buffer` dereference to a raw buffer, which is also what is bound to shaders.
+///
+/// A buffer holds its own handle to the device that created it, so it does not borrow the
+/// device and can be stored, moved, and returned freely.
+pub struct RawBuffer {
+    id: u64,
+    device: Device,
     buffer_type: BufferType,
     contents: BufferContents,
     buffer: wgpu::Buffer,
-    // staging: Option<wgpu::Buffer>,
+}
+
+/// A buffer holding a sequence of elements of type `T`.
+///
+/// Create a buffer using the `Device::create_buffer` method. The buffer remembers its element
+/// type and length, so reading and writing it is type-checked. It dereferences to the
+/// underlying [RawBuffer].
+pub struct Buffer<T> {
+    raw: RawBuffer,
+    len: usize,
+    _marker: PhantomData<T>,
 }
 
 /// Specifies how a buffer is initialized.
-pub enum BufferInit<T>
-where
-    T: ShaderType + WriteInto,
-{
-    /// Initialize a buffer with a fixed size.
+pub enum BufferInit<'a, T> {
+    /// Initialize a buffer with a fixed number of elements.
     WithSize(usize),
     /// Initialize a buffer with initial data.
-    WithData(T),
+    WithData(&'a [T]),
 }
 
 pub enum BufferContents {
-    Size(u32),
+    Size(u64),
     Data(Vec<u8>),
 }
 
 impl BufferContents {
-    pub fn size(&self) -> u32 {
+    pub fn size(&self) -> u64 {
         match self {
             BufferContents::Size(size) => *size,
-            BufferContents::Data(data) => data.len() as u32,
+            BufferContents::Data(data) => data.len() as u64,
         }
     }
 }
 
+/// An error from creating, reading, or writing a buffer.
 #[derive(Error, Debug)]
 pub enum BufferError {
+    /// The buffer is not an output buffer, so it cannot be read.
     #[error("Cannot read from a non-output buffer.")]
     NotOutputBuffer,
-    // #[error("Not enough memory space in output")]
-    // NotEnoughOutputSpace,
+    /// The batch a readback was recorded in has not been submitted yet, or submitting it failed.
+    #[error("Cannot read from a readback whose batch has not been submitted.")]
+    NotSubmitted,
+    /// More elements were written to a buffer than it can hold.
+    #[error("Cannot write {found} elements to a buffer of {len} elements.")]
+    TooManyElements {
+        /// The number of elements the buffer holds.
+        len: usize,
+        /// The number of elements written.
+        found: usize,
+    },
+    /// The output given to a read does not have the same size as the data read.
+    #[error("The output is {found} bytes, but {expected} bytes are read from the buffer.")]
+    OutputSizeMismatch {
+        /// The size of the data read in bytes.
+        expected: u64,
+        /// The size of the output in bytes.
+        found: u64,
+    },
+    /// A range of elements does not lie within the buffer.
+    #[error("The range {range:?} is out of bounds for a buffer of {len} elements.")]
+    InvalidRange {
+        /// The range of element indices.
+        range: Range<usize>,
+        /// The number of elements the buffer holds.
+        len: usize,
+    },
+    /// A range of bytes does not lie within the buffer.
+    #[error("Cannot access {size} bytes at offset {offset} of a buffer of {buffer_size} bytes.")]
+    OutOfRange {
+        /// The byte offset of the range.
+        offset: u64,
+        /// The size of the range in bytes.
+        size: u64,
+        /// The size of the buffer in bytes.
+        buffer_size: u64,
+    },
+    /// The offset or size of a range of bytes is not a multiple of
+    /// `wgpu::COPY_BUFFER_ALIGNMENT`.
+    #[error(
+        "The offset ({offset}) and size ({size}) of a copy must be multiples of {} bytes.",
+        wgpu::COPY_BUFFER_ALIGNMENT
+    )]
+    Misaligned {
+        /// The byte offset of the range.
+        offset: u64,
+        /// The size of the range in bytes.
+        size: u64,
+    },
+    /// A buffer cannot be copied to itself.
+    #[error("Cannot copy between two ranges of the same buffer.")]
+    CopyToSelf,
+    /// The size of a buffer (in bytes) does not fit in a `u64`.
+    #[error("The size of a buffer of {len} elements overflows a u64.")]
+    SizeOverflow {
+        /// The number of elements in the buffer.
+        len: usize,
+    },
+    /// The size of a buffer exceeds `Limits::max_buffer_size`.
+    #[error("A buffer of {size} bytes exceeds the maximum buffer size of {limit} bytes.")]
+    TooLarge {
+        /// The size of the buffer in bytes.
+        size: u64,
+        /// The maximum size of a buffer on the device in bytes.
+        limit: u64,
+    },
+    /// The buffer could not be mapped to read its contents, for example because the device
+    /// was lost.
+    #[error("Could not map the buffer for reading: {0}")]
+    MapFailed(wgpu::BufferAsyncError),
 }
 
-impl<'a> Buffer<'a> {
+impl RawBuffer {
     /// Used to create a new buffer. However, this method is sealed.
     /// Use `Device::create_buffer` instead.
     pub(crate) fn new(
         label: Option<&str>,
-        device: &'a Device,
+        device: &Device,
         buffer_type: BufferType,
         contents: BufferContents,
     ) -> Self {
-        let usage = {
-            let buffer_type = match buffer_type {
-                BufferType::StorageBuffer { .. } => wgpu::BufferUsages::STORAGE,
-                BufferType::UniformBuffer => wgpu::BufferUsages::UNIFORM,
-            };
-            buffer_type | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST
-        };
+        let usage = usage(buffer_type);
         let buffer = match &contents {
             BufferContents::Size(size) => device.device().create_buffer(&BufferDescriptor {
                 label,
-                size: *size as u64,
+                size: *size,
                 usage,
                 mapped_at_creation: false,
             }),
@@ -103,14 +185,39 @@ impl<'a> Buffer<'a> {
         device.queue().submit([]);
 
         Self {
-            device,
+            id: next_id(),
+            device: device.clone(),
             buffer_type,
             contents,
             buffer,
         }
     }
+    /// Used to create a new buffer initialized with the given bytes, which are copied straight
+    /// into the buffer while it is mapped at creation. Unlike with `RawBuffer::new`, the bytes
+    /// are not kept around. Use `Device::create_pod_buffer` instead.
+    pub(crate) fn from_bytes(
+        label: Option<&str>,
+        device: &Device,
+        buffer_type: BufferType,
+        data: &[u8],
+    ) -> Self {
+        let buffer = device.device().create_buffer_init(&BufferInitDescriptor {
+            label,
+            contents: data,
+            usage: usage(buffer_type),
+        });
+        device.queue().submit([]);
+
+        Self {
+            id: next_id(),
+            device: device.clone(),
+            buffer_type,
+            contents: BufferContents::Size(data.len() as u64),
+            buffer,
+        }
+    }
     /// Get the size of the buffer (in bytes).
-    pub fn size(&self) -> u32 {
+    pub fn size(&self) -> u64 {
         self.contents.size()
     }
     /// Check if the buffer is an output buffer (i.e., readable from the CPU
@@ -121,7 +228,10 @@ impl<'a> Buffer<'a> {
             BufferType::StorageBuffer { output: true, .. }
         )
     }
-    /// Get a reference to the data stored in the buffer (as bytes).
+    /// Get a reference to the data the buffer was created with (as bytes).
+    ///
+    /// Returns `None` for buffers created with a size, and for buffers created from
+    /// plain-old-data using `Device::create_pod_buffer`.
     pub fn data(&self) -> Option<&Vec<u8>> {
         match &self.contents {
             BufferContents::Size(_) => None,
@@ -141,8 +251,21 @@ impl<'a> Buffer<'a> {
     pub(crate) fn buffer(&self) -> &wgpu::Buffer {
         &self.buffer
     }
+    /// Get the unique identifier of the buffer.
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+    /// Get the address space the buffer can be bound to in a shader.
+    pub(crate) fn address_space(&self) -> AddressSpace {
+        match self.buffer_type {
+            BufferType::StorageBuffer { read_only, .. } => AddressSpace::Storage { read_only },
+            BufferType::UniformBuffer => AddressSpace::Uniform,
+        }
+    }
     /// Write data to the buffer.
-    pub fn write<T>(&self, data: &T)
+    ///
+    /// Returns an error if the data does not fit in the buffer.
+    pub fn write<T>(&self, data: &T) -> Result<(), BufferError>
     where
         T: ShaderType + WriteInto,
     {
@@ -158,40 +281,595 @@ impl<'a> Buffer<'a> {
                 buffer.into_inner()
             }
         };
+        self.write_bytes(0, &data)
+    }
+    /// Write bytes to the buffer, starting at the given byte offset.
+    pub(crate) fn write_bytes(&self, offset: u64, data: &[u8]) -> Result<(), BufferError> {
+        self.check_range(offset, data.len() as u64)?;
         // TODO: Improve to use write_buffer_with
-        self.device.queue().write_buffer(&self.buffer, 0, &data);
+        self.device.queue().write_buffer(&self.buffer, offset, data);
         self.device.queue().submit([]);
+        Ok(())
     }
     /// Read the data in the buffer. This makes the buffer temporarily accessible
     /// to the CPU to write the buffer contents to the output mutable reference.
+    ///
+    /// Returns an error if the size of the output does not match the size of the buffer.
     pub async fn read<T>(&self, output: &mut T) -> Result<(), BufferError>
     where
         T: ShaderType + ReadFrom,
     {
+        if output.size().get() != self.size() {
+            return Err(BufferError::OutputSizeMismatch {
+                expected: self.size(),
+                found: output.size().get(),
+            });
+        }
+        self.read_bytes(0, self.size(), output).await
+    }
+    /// Read the data in the buffer as a sequence of elements of type `T`, returning them in
+    /// a newly allocated vector with as many elements as fit in the buffer.
+    pub async fn read_to_vec<T>(&self) -> Result<Vec<T>, BufferError>
+    where
+        T: ShaderType + ShaderSize + ReadFrom + CreateFrom,
+    {
+        let mut output = Vec::new();
+        self.read_bytes(0, self.size(), &mut output).await?;
+        Ok(output)
+    }
+    /// Read `size` bytes of the buffer, starting at the given byte offset, into `output`.
+    pub(crate) async fn read_bytes<T>(
+        &self,
+        offset: u64,
+        size: u64,
+        output: &mut T,
+    ) -> Result<(), BufferError>
+    where
+        T: ShaderType + ReadFrom,
+    {
+        self.read_bytes_with(offset, size, |bytes| {
+            StorageBuffer::new(bytes).read(output).unwrap();
+        })
+        .await
+    }
+    /// Read `size` bytes of the buffer, starting at the given byte offset, passing them to `f`
+    /// while they are mapped.
+    pub(crate) async fn read_bytes_with<R>(
+        &self,
+        offset: u64,
+        size: u64,
+        f: impl FnOnce(&[u8]) -> R,
+    ) -> Result<R, BufferError> {
         if !self.output() {
             return Err(BufferError::NotOutputBuffer);
         }
-        self.device.copy_to_staging(self);
-
-        // TODO: Return an error if the output is not large enough to hold the buffer's data.
-        let staging = self.device.staging().borrow();
-        if let Some(staging) = staging.as_ref() {
-            let output_size = self.size() as u64;
-            let slice = staging.slice(..output_size);
-            let (tx, rx) = flume::bounded(1);
-            slice.map_async(wgpu::MapMode::Read, move |r| tx.send(r).unwrap());
-            self.device
-                .device()
-                .poll(wgpu::Maintain::wait())
-                .panic_on_timeout();
-            rx.recv_async().await.unwrap().unwrap();
-            {
-                let view = slice.get_mapped_range();
-                let buffer = StorageBuffer::new(&*view);
-                buffer.read(output).unwrap();
-            }
-            staging.unmap();
+        self.check_range(offset, size)?;
+        let mut staging = self.device.copy_to_staging(self, offset, size);
+        read_staging_with(&self.device, &mut staging, size, f).await
+    }
+    /// Checks that `size` bytes starting at `offset` lie within the buffer and can be
+    /// copied, that is, both are multiples of `wgpu::COPY_BUFFER_ALIGNMENT`.
+    pub(crate) fn check_range(&self, offset: u64, size: u64) -> Result<(), BufferError> {
+        let end = offset.checked_add(size);
+        if end.is_none_or(|end| end > self.size()) {
+            return Err(BufferError::OutOfRange {
+                offset,
+                size,
+                buffer_size: self.size(),
+            });
+        }
+        if !offset.is_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT)
+            || !size.is_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT)
+        {
+            return Err(BufferError::Misaligned { offset, size });
         }
         Ok(())
     }
 }
+
+impl<T> Buffer<T>
+where
+    T: ShaderType + ShaderSize,
+{
+    /// Wraps a raw buffer holding `len` elements. Use `Device::create_buffer` instead.
+    pub(crate) fn new(raw: RawBuffer, len: usize) -> Self {
+        Self {
+            raw,
+            len,
+            _marker: PhantomData,
+        }
+    }
+    /// Get the number of elements in the buffer.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    /// Check if the buffer holds no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Write elements to the start of the buffer.
+    ///
+    /// Returns an error if there are more elements than the buffer can hold.
+    pub fn write(&self, data: &[T]) -> Result<(), BufferError>
+    where
+        T: WriteInto,
+    {
+        self.write_at(0, data)
+    }
+    /// Write elements to the buffer, starting at the element with the given index.
+    /// The rest of the buffer is left untouched.
+    ///
+    /// Returns an error if the elements do not fit in the buffer from that index onwards,
+    /// or if they do not start and end on a multiple of `wgpu::COPY_BUFFER_ALIGNMENT` bytes.
+    pub fn write_at(&self, offset: usize, data: &[T]) -> Result<(), BufferError>
+    where
+        T: WriteInto,
+    {
+        if offset
+            .checked_add(data.len())
+            .is_none_or(|end| end > self.len)
+        {
+            return Err(BufferError::TooManyElements {
+                len: self.len,
+                found: offset.saturating_add(data.len()),
+            });
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+        let offset = offset as u64 * element_stride::<T>(self.buffer_type());
+        self.raw
+            .write_bytes(offset, &encode(self.buffer_type(), data)?)
+    }
+    /// Set every element of the buffer to the given value.
+    ///
+    /// Filling a buffer with zeros is done on the GPU without uploading any data.
+    /// Use `CommandBatch::fill` to fill a buffer as part of a batch.
+    pub fn fill(&self, value: &T) -> Result<(), BufferError>
+    where
+        T: WriteInto,
+    {
+        let bytes = encode_repeated(self.buffer_type(), value, self.len)?;
+        if bytes.iter().all(|&byte| byte == 0) {
+            return self.device.clear_buffer(self);
+        }
+        self.raw.write_bytes(0, &bytes)
+    }
+    /// Read the elements in the buffer. This makes the buffer temporarily accessible
+    /// to the CPU to write the buffer contents to the output vector.
+    ///
+    /// Returns an error if the output vector does not have the same length as the buffer.
+    pub async fn read(&self, output: &mut Vec<T>) -> Result<(), BufferError>
+    where
+        T: ReadFrom + CreateFrom,
+    {
+        self.read_range(0..self.len, output).await
+    }
+    /// Read the elements in the buffer into a newly allocated vector.
+    pub async fn read_to_vec(&self) -> Result<Vec<T>, BufferError>
+    where
+        T: ReadFrom + CreateFrom,
+    {
+        let mut output = Vec::with_capacity(self.len);
+        self.read_elements(0..self.len, &mut output).await?;
+        Ok(output)
+    }
+    /// Read the elements in the given range of indices into the output vector, which must
+    /// have the same length as the range. Only the requested range is copied from the GPU.
+    ///
+    /// Returns an error if the range is out of bounds, if it does not start and end on
+    /// a multiple of `wgpu::COPY_BUFFER_ALIGNMENT` bytes, or if the length of the output
+    /// does not match.
+    pub async fn read_range(
+        &self,
+        range: Range<usize>,
+        output: &mut Vec<T>,
+    ) -> Result<(), BufferError>
+    where
+        T: ReadFrom + CreateFrom,
+    {
+        if output.len() != range.len() {
+            let stride = element_stride::<T>(self.buffer_type());
+            return Err(BufferError::OutputSizeMismatch {
+                expected: range.len() as u64 * stride,
+                found: output.len() as u64 * stride,
+            });
+        }
+        self.read_elements(range, output).await
+    }
+    /// Read the elements in the given range of indices, replacing the contents of the
+    /// output vector.
+    async fn read_elements(
+        &self,
+        range: Range<usize>,
+        output: &mut Vec<T>,
+    ) -> Result<(), BufferError>
+    where
+        T: ReadFrom + CreateFrom,
+    {
+        if range.start > range.end || range.end > self.len {
+            return Err(BufferError::InvalidRange {
+                range,
+                len: self.len,
+            });
+        }
+        if range.is_empty() {
+            output.clear();
+            return Ok(());
+        }
+        let stride = element_stride::<T>(self.buffer_type());
+        self.raw
+            .read_bytes(
+                range.start as u64 * stride,
+                range.len() as u64 * stride,
+                output,
+            )
+            .await
+    }
+}
+
+impl<T> Buffer<T>
+where
+    T: ShaderType + ShaderSize + Pod,
+{
+    /// Write plain-old-data elements to the start of the buffer.
+    ///
+    /// See [Buffer::write_pod_at].
+    pub fn write_pod(&self, data: &[T]) -> Result<(), BufferError>
+    where
+        T: WriteInto,
+    {
+        self.write_pod_at(0, data)
+    }
+    /// Write plain-old-data elements to the buffer, starting at the element with the
+    /// given index.
+    ///
+    /// This works like [Buffer::write_at], except that the bytes of the elements are uploaded
+    /// as-is instead of being serialized first, which avoids an extra copy of the data.
+    /// If the layout of `T` in the shader has padding that Rust does not have (like `u32`
+    /// elements in a uniform buffer, which are 16 bytes apart), the elements are serialized
+    /// as usual.
+    pub fn write_pod_at(&self, offset: usize, data: &[T]) -> Result<(), BufferError>
+    where
+        T: WriteInto,
+    {
+        if !matches_pod_layout::<T>(self.buffer_type()) {
+            return self.write_at(offset, data);
+        }
+        if offset
+            .checked_add(data.len())
+            .is_none_or(|end| end > self.len)
+        {
+            return Err(BufferError::TooManyElements {
+                len: self.len,
+                found: offset.saturating_add(data.len()),
+            });
+        }
+        self.raw
+            .write_bytes((offset * size_of::<T>()) as u64, bytemuck::cast_slice(data))
+    }
+    /// Read the elements in the buffer straight into a slice of plain-old-data, which must
+    /// have the same length as the buffer.
+    ///
+    /// See [Buffer::read_pod_range].
+    pub async fn read_pod(&self, output: &mut [T]) -> Result<(), BufferError>
+    where
+        T: ReadFrom + CreateFrom,
+    {
+        self.read_pod_range(0..self.len, output).await
+    }
+    /// Read the elements in the given range of indices straight into a slice of plain-old-data,
+    /// which must have the same length as the range.
+    ///
+    /// This works like [Buffer::read_range], except that the bytes of the elements are copied
+    /// from the mapped staging buffer as-is instead of being deserialized. If the layout of `T`
+    /// in the shader has padding that Rust does not have, the elements are deserialized
+    /// as usual.
+    pub async fn read_pod_range(
+        &self,
+        range: Range<usize>,
+        output: &mut [T],
+    ) -> Result<(), BufferError>
+    where
+        T: ReadFrom + CreateFrom,
+    {
+        let stride = element_stride::<T>(self.buffer_type());
+        if output.len() != range.len() {
+            return Err(BufferError::OutputSizeMismatch {
+                expected: range.len() as u64 * stride,
+                found: output.len() as u64 * stride,
+            });
+        }
+        if !matches_pod_layout::<T>(self.buffer_type()) {
+            let mut elements = Vec::with_capacity(range.len());
+            self.read_elements(range, &mut elements).await?;
+            output.copy_from_slice(&elements);
+            return Ok(());
+        }
+        if range.start > range.end || range.end > self.len {
+            return Err(BufferError::InvalidRange {
+                range,
+                len: self.len,
+            });
+        }
+        if range.is_empty() {
+            return Ok(());
+        }
+        self.raw
+            .read_bytes_with(
+                range.start as u64 * stride,
+                range.len() as u64 * stride,
+                |bytes| bytemuck::cast_slice_mut(output).copy_from_slice(bytes),
+            )
+            .await
+    }
+}
+
+impl<T> Deref for Buffer<T> {
+    type Target = RawBuffer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.raw
+    }
+}
+
+impl<T> DerefMut for Buffer<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.raw
+    }
+}
+
+impl<T> AsRef<RawBuffer> for Buffer<T> {
+    fn as_ref(&self) -> &RawBuffer {
+        &self.raw
+    }
+}
+
+impl<T> AsMut<RawBuffer> for Buffer<T> {
+    fn as_mut(&mut self) -> &mut RawBuffer {
+        &mut self.raw
+    }
+}
+
+impl AsRef<RawBuffer> for RawBuffer {
+    fn as_ref(&self) -> &RawBuffer {
+        self
+    }
+}
+
+impl AsMut<RawBuffer> for RawBuffer {
+    fn as_mut(&mut self) -> &mut RawBuffer {
+        self
+    }
+}
+
+/// Get the buffer usages of a buffer of the given type.
+fn usage(buffer_type: BufferType) -> wgpu::BufferUsages {
+    let usage = match buffer_type {
+        BufferType::StorageBuffer { .. } => wgpu::BufferUsages::STORAGE,
+        BufferType::UniformBuffer => wgpu::BufferUsages::UNIFORM,
+    };
+    usage | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST
+}
+
+/// Check if elements of type `T` have the same memory layout in Rust as in a buffer of the
+/// given type, such that their bytes can be copied as-is.
+///
+/// A `Pod` type has no padding, so its layout only matches if the shader does not need any
+/// padding either, which is the case exactly when the sizes match.
+pub(crate) fn matches_pod_layout<T>(buffer_type: BufferType) -> bool
+where
+    T: ShaderType + ShaderSize + Pod,
+{
+    size_of::<T>() as u64 == element_stride::<T>(buffer_type)
+}
+
+/// Get the distance (in bytes) between consecutive elements of type `T` in a buffer of the
+/// given type. Elements of arrays in uniform buffers are aligned to 16 bytes.
+pub(crate) fn element_stride<T>(buffer_type: BufferType) -> u64
+where
+    T: ShaderType + ShaderSize,
+{
+    let stride = <[T]>::calculate_size_for(1).get();
+    match buffer_type {
+        BufferType::StorageBuffer { .. } => stride,
+        BufferType::UniformBuffer => stride.next_multiple_of(16),
+    }
+}
+
+/// Get the size (in bytes) of a buffer of the given type holding `len` elements of type `T`,
+/// laid out the way a shader expects them.
+///
+/// Storage buffers are sized like a runtime-sized array, while uniform buffers are sized like
+/// a fixed-size array whose last element is not padded. A buffer always has room for at least
+/// one element.
+///
+/// Returns an error if the size does not fit in a `u64`.
+pub(crate) fn buffer_size<T>(buffer_type: BufferType, len: usize) -> Result<u64, BufferError>
+where
+    T: ShaderType + ShaderSize,
+{
+    let stride = element_stride::<T>(buffer_type);
+    let size = u64::try_from(len.max(1))
+        .ok()
+        .and_then(|elements| match buffer_type {
+            BufferType::StorageBuffer { .. } => stride.checked_mul(elements),
+            BufferType::UniformBuffer => stride
+                .checked_mul(elements - 1)?
+                .checked_add(T::min_size().get()),
+        });
+    size.ok_or(BufferError::SizeOverflow { len })
+}
+
+/// Serializes `len` copies of an element using the memory layout of the given buffer type.
+pub(crate) fn encode_repeated<T>(
+    buffer_type: BufferType,
+    value: &T,
+    len: usize,
+) -> Result<Vec<u8>, BufferError>
+where
+    T: ShaderType + ShaderSize + WriteInto,
+{
+    let mut element = encode(buffer_type, std::slice::from_ref(value))?;
+    element.resize(element_stride::<T>(buffer_type) as usize, 0);
+    let mut bytes = element.repeat(len);
+    bytes.truncate(buffer_size::<T>(buffer_type, len)? as usize);
+    Ok(bytes)
+}
+
+/// Serializes elements using the memory layout of the given buffer type.
+pub(crate) fn encode<T>(buffer_type: BufferType, data: &[T]) -> Result<Vec<u8>, BufferError>
+where
+    T: ShaderType + ShaderSize + WriteInto,
+{
+    Ok(match buffer_type {
+        BufferType::StorageBuffer { .. } => {
+            let mut buffer = StorageBuffer::new(vec![]);
+            buffer.write(data).unwrap();
+            buffer.into_inner()
+        }
+        // Runtime-sized arrays cannot be used in uniform buffers, so the elements are written
+        // one by one as if they were in a fixed-size array.
+        BufferType::UniformBuffer => {
+            let stride = element_stride::<T>(buffer_type) as usize;
+            let mut bytes = vec![0; buffer_size::<T>(buffer_type, data.len())? as usize];
+            for (idx, element) in data.iter().enumerate() {
+                let mut buffer = UniformBuffer::new(&mut bytes[idx * stride..]);
+                buffer.write(element).unwrap();
+            }
+            bytes
+        }
+    })
+}
+
+/// Maps the first `size` bytes of a staging buffer, reads them into `output`, then unmaps
+/// the staging buffer again.
+pub(crate) async fn read_staging<T>(
+    device: &Device,
+    staging: &mut StagingBuffer<'_>,
+    size: u64,
+    output: &mut T,
+) -> Result<(), BufferError>
+where
+    T: ShaderType + ReadFrom,
+{
+    read_staging_with(device, staging, size, |bytes| {
+        StorageBuffer::new(bytes).read(output).unwrap();
+    })
+    .await
+}
+
+/// Maps the first `size` bytes of a staging buffer, passes them to `f`, then unmaps
+/// the staging buffer again. Once the staging buffer is unmapped, it is marked as idle,
+/// so that it returns to the pool when dropped.
+///
+/// This does not block: the mapping is driven by the device's background poller (or by
+/// `Device::poll`), and the future resolves once the staging buffer has been mapped.
+///
+/// Returns an error if the staging buffer could not be mapped, in which case it is not marked
+/// as idle, so that it is freed rather than reused.
+pub(crate) async fn read_staging_with<R>(
+    device: &Device,
+    staging: &mut StagingBuffer<'_>,
+    size: u64,
+    f: impl FnOnce(&[u8]) -> R,
+) -> Result<R, BufferError> {
+    let slice = staging.slice(..size);
+    let (tx, rx) = flume::bounded(1);
+    let pending = device.poller().begin();
+    slice.map_async(wgpu::MapMode::Read, move |r| {
+        let _ = tx.send(r);
+        drop(pending);
+    });
+    // The sender is only dropped without sending if the device is destroyed, in which case
+    // the map never completes.
+    let mapped = rx.recv_async().await.unwrap_or(Err(wgpu::BufferAsyncError));
+    mapped.map_err(BufferError::MapFailed)?;
+    let result = f(&slice.get_mapped_range());
+    staging.unmap();
+    staging.set_idle(true);
+    Ok(result)
+}
+
+impl Drop for RawBuffer {
+    fn drop(&mut self) {
+        self.device.cache().lock().unwrap().evict_buffer(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BufferType, buffer_size, element_stride, encode};
+
+    const STORAGE: BufferType = BufferType::StorageBuffer {
+        output: false,
+        read_only: true,
+    };
+    const UNIFORM: BufferType = BufferType::UniformBuffer;
+
+    /// A `vec3<f32>`, which is 12 bytes but aligned to 16 bytes.
+    struct Vec3([f32; 3]);
+
+    impl AsRef<[f32; 3]> for Vec3 {
+        fn as_ref(&self) -> &[f32; 3] {
+            &self.0
+        }
+    }
+
+    impl AsMut<[f32; 3]> for Vec3 {
+        fn as_mut(&mut self) -> &mut [f32; 3] {
+            &mut self.0
+        }
+    }
+
+    impl From<[f32; 3]> for Vec3 {
+        fn from(parts: [f32; 3]) -> Self {
+            Self(parts)
+        }
+    }
+
+    encase::impl_vector!(3, Vec3, f32; using AsRef AsMut From);
+
+    fn words(bytes: &[u8]) -> Vec<u32> {
+        bytes
+            .chunks_exact(4)
+            .map(|word| u32::from_ne_bytes(word.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn vec3_elements_are_padded() {
+        assert_eq!(element_stride::<Vec3>(STORAGE), 16);
+        assert_eq!(element_stride::<Vec3>(UNIFORM), 16);
+        assert_eq!(buffer_size::<Vec3>(STORAGE, 2).unwrap(), 32);
+        let bytes = encode(STORAGE, &[Vec3([1.0, 2.0, 3.0]), Vec3([4.0, 5.0, 6.0])]).unwrap();
+        let floats = words(&bytes)
+            .into_iter()
+            .map(f32::from_bits)
+            .collect::<Vec<_>>();
+        assert_eq!(floats, [1.0, 2.0, 3.0, 0.0, 4.0, 5.0, 6.0, 0.0]);
+    }
+
+    #[test]
+    fn uniform_array_stride_is_16_bytes() {
+        assert_eq!(element_stride::<u32>(STORAGE), 4);
+        assert_eq!(element_stride::<u32>(UNIFORM), 16);
+        assert_eq!(buffer_size::<u32>(STORAGE, 3).unwrap(), 12);
+        let bytes = encode(UNIFORM, &[1u32, 2, 3]).unwrap();
+        assert_eq!(words(&bytes), [1, 0, 0, 0, 2, 0, 0, 0, 3]);
+    }
+
+    #[test]
+    fn last_uniform_element_is_not_padded() {
+        assert_eq!(buffer_size::<u32>(UNIFORM, 3).unwrap(), 36);
+        assert_eq!(buffer_size::<Vec3>(UNIFORM, 2).unwrap(), 28);
+        let bytes = encode(UNIFORM, &[Vec3([1.0, 2.0, 3.0]), Vec3([4.0, 5.0, 6.0])]).unwrap();
+        assert_eq!(bytes.len(), 28);
+    }
+
+    #[test]
+    fn empty_buffers_hold_one_element() {
+        assert_eq!(buffer_size::<u32>(STORAGE, 0).unwrap(), 4);
+        assert_eq!(buffer_size::<Vec3>(UNIFORM, 0).unwrap(), 12);
+    }
+}
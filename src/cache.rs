@@ -0,0 +1,71 @@
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+/// Generates a process-wide unique identifier, used for keying cached GPU objects
+/// on the shader modules and buffers they were created from.
+pub(crate) fn next_id() -> u64 {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// A compute pipeline along with the bind group layouts it was created with.
+pub(crate) struct CachedPipeline {
+    pub(crate) bind_group_layouts: Vec<wgpu::BindGroupLayout>,
+    pub(crate) pipeline: wgpu::ComputePipeline,
+}
+
+/// Cache of compute pipelines and bind groups owned by a `Device`.
+///
+/// Pipelines are keyed on the shader module they are created from, while bind groups are
+/// keyed on the shader module and the exact buffers bound to it.
+/// Entries are evicted when the shader module or any of the buffers they refer to are dropped.
+#[derive(Default)]
+pub(crate) struct PipelineCache {
+    pipelines: HashMap<u64, Arc<CachedPipeline>>,
+    bind_groups: HashMap<(u64, Vec<u64>), Arc<[wgpu::BindGroup]>>,
+}
+
+impl PipelineCache {
+    /// Gets the pipeline for the given shader module, creating it if necessary.
+    ///
+    /// Nothing is cached if creating the pipeline fails.
+    pub(crate) fn pipeline<E>(
+        &mut self,
+        module_id: u64,
+        create: impl FnOnce() -> Result<CachedPipeline, E>,
+    ) -> Result<Arc<CachedPipeline>, E> {
+        Ok(match self.pipelines.entry(module_id) {
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => entry.insert(Arc::new(create()?)).clone(),
+        })
+    }
+    /// Gets the bind groups for the given shader module and buffers, creating them if necessary.
+    ///
+    /// Nothing is cached if creating the bind groups fails.
+    pub(crate) fn bind_groups<E>(
+        &mut self,
+        module_id: u64,
+        buffer_ids: Vec<u64>,
+        create: impl FnOnce() -> Result<Vec<wgpu::BindGroup>, E>,
+    ) -> Result<Arc<[wgpu::BindGroup]>, E> {
+        Ok(match self.bind_groups.entry((module_id, buffer_ids)) {
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => entry.insert(create()?.into()).clone(),
+        })
+    }
+    /// Removes all cached objects created from the given shader module.
+    pub(crate) fn evict_module(&mut self, module_id: u64) {
+        self.pipelines.remove(&module_id);
+        self.bind_groups.retain(|(id, _), _| *id != module_id);
+    }
+    /// Removes all cached bind groups that the given buffer is a part of.
+    pub(crate) fn evict_buffer(&mut self, buffer_id: u64) {
+        self.bind_groups
+            .retain(|(_, ids), _| !ids.contains(&buffer_id));
+    }
+}
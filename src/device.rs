@@ -1,34 +1,203 @@
-use std::cell::RefCell;
+use std::{
+    num::NonZeroU64,
+    sync::{Arc, Mutex},
+};
 
-use encase::{ShaderType, StorageBuffer, UniformBuffer, internal::WriteInto};
+use bytemuck::Pod;
+use encase::{ShaderSize, ShaderType, internal::WriteInto};
 use regex::Regex;
 use thiserror::Error;
 
 use crate::{
-    DeviceInfo, Limits,
-    buffer::{Buffer, BufferContents, BufferInit, BufferType},
-    types::ShaderModule,
+    DeviceInfo, Features, Limits,
+    batch::CommandBatch,
+    bindings::BufferBindings,
+    buffer::{
+        Buffer, BufferContents, BufferError, BufferInit, BufferType, RawBuffer, buffer_size,
+        encode, matches_pod_layout,
+    },
+    cache::{CachedPipeline, PipelineCache},
+    poller::Poller,
+    shader::{AddressSpace, ShaderBinding, ShaderDiagnostic, ShaderError, ShaderModule, reflect},
+    staging::{StagingBuffer, StagingPool},
 };
 
 /// Effectively a reference to a GPU. Obtain a device by using `Instance::autoselect`
 /// or `Instance::devices`.
+///
+/// A device is a cheap handle: cloning it gives another handle to the same GPU, sharing its
+/// caches and staging buffers. Buffers hold a handle to the device that created them, so they
+/// are not tied to the lifetime of a borrowed device. A device is also `Send` and `Sync`,
+/// so it can be shared between threads.
+#[derive(Clone)]
 pub struct Device {
+    inner: Arc<DeviceInner>,
+}
+
+struct DeviceInner {
     adapter: wgpu::Adapter,
-    device: wgpu::Device,
+    device: Arc<wgpu::Device>,
     queue: wgpu::Queue,
+    poller: Poller,
     limits: Limits,
-    staging_buffer: RefCell<Option<wgpu::Buffer>>,
-    staging_size: RefCell<Option<u32>>,
+    features: Features,
+    staging: StagingPool,
+    cache: Arc<Mutex<PipelineCache>>,
+    /// Serializes the use of error scopes, which are not available on WebAssembly.
+    #[cfg(not(target_arch = "wasm32"))]
+    error_scopes: Mutex<()>,
+}
+
+// Sharing devices, shader modules, and buffers between threads is part of the public API.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Device>();
+    assert_send_sync::<ShaderModule>();
+    assert_send_sync::<RawBuffer>();
+    assert_send_sync::<Buffer<u32>>();
+};
+
+/// Folds a dispatch of `workgroups` workgroups along the x dimension into a 2D or 3D grid
+/// with no more than `limit` workgroups per dimension, keeping the number of excess
+/// workgroups small. Returns `None` if the workgroups do not fit in a 3D grid.
+fn fold_dispatch(workgroups: u32, limit: u32) -> Option<[u32; 3]> {
+    let z = workgroups.div_ceil(limit.saturating_mul(limit).max(1));
+    let per_layer = workgroups.div_ceil(z);
+    let y = per_layer.div_ceil(limit.max(1));
+    let x = per_layer.div_ceil(y);
+    (z <= limit).then_some([x, y, z])
+}
+
+/// An error from executing a compute shader.
+#[derive(Error, Debug)]
+pub enum ExecuteError {
+    /// At least one of the dispatch dimensions exceeds the limit of the device.
+    #[error(
+        "Dispatch dimensions {dimensions:?} exceed the limit of {limit} workgroups per dimension"
+    )]
+    DispatchTooLarge {
+        /// The x, y, and z dispatch dimensions.
+        dimensions: [u32; 3],
+        /// The maximum allowed value of each dimension.
+        limit: u32,
+    },
+    /// The number of bind groups given does not match the number used by the shader.
+    #[error("The shader uses {expected} bind group(s), but {found} were given")]
+    GroupCountMismatch {
+        /// The number of bind groups used by the shader.
+        expected: usize,
+        /// The number of bind groups given.
+        found: usize,
+    },
+    /// The number of buffers in a bind group does not match the number of bindings
+    /// the shader uses in that group.
+    #[error("The shader uses {expected} binding(s) in group {group}, but {found} were given")]
+    BindingCountMismatch {
+        /// The bind group index.
+        group: u32,
+        /// The number of bindings used by the shader in the group.
+        expected: usize,
+        /// The number of buffers given for the group.
+        found: usize,
+    },
+    /// A buffer cannot be bound to a binding as their types are incompatible.
+    #[error(
+        "A {found:?} buffer cannot be bound to `{}` (@group({}) @binding({})), which is {:?}",
+        binding.name.as_deref().unwrap_or_default(),
+        binding.group,
+        binding.binding,
+        binding.address_space
+    )]
+    BindingTypeMismatch {
+        /// The binding the buffer was bound to.
+        binding: ShaderBinding,
+        /// The type of the buffer.
+        found: AddressSpace,
+    },
+    /// A buffer is smaller than the minimum size of the binding it is bound to.
+    #[error(
+        "A buffer of {size} bytes is too small for `{}` (@group({}) @binding({})), which needs at least {} bytes",
+        binding.name.as_deref().unwrap_or_default(),
+        binding.group,
+        binding.binding,
+        binding.min_size
+    )]
+    BufferTooSmall {
+        /// The binding the buffer was bound to.
+        binding: ShaderBinding,
+        /// The size of the buffer in bytes.
+        size: u64,
+    },
+    /// A buffer was bound to a name that is not a buffer binding in the shader.
+    #[error("The shader has no buffer binding named `{0}`")]
+    UnknownBinding(String),
+    /// More than one buffer was bound to the same name, or a buffer was bound to the problem
+    /// size uniform that `Device::execute_over` binds.
+    #[error("More than one buffer was bound to `{0}`")]
+    DuplicateBinding(String),
+    /// No buffer was bound to a binding in the shader.
+    #[error(
+        "No buffer was bound to `{}` (@group({}) @binding({}))",
+        .0.name.as_deref().unwrap_or_default(),
+        .0.group,
+        .0.binding
+    )]
+    UnboundBinding(ShaderBinding),
+    /// The push constants set on a batch do not match the size of the push constants
+    /// used by the shader.
+    #[error("The shader uses {expected} bytes of push constants, but {found} bytes were set")]
+    PushConstantSizeMismatch {
+        /// The size of the push constants used by the shader in bytes.
+        expected: u32,
+        /// The size of the push constants set in bytes.
+        found: usize,
+    },
+    /// The device does not have the features needed for a command.
+    #[error("The device does not have the required features: {0}")]
+    MissingFeatures(Features),
+    /// wgpu reported a validation error.
+    #[error("Validation error: {0}")]
+    Validation(String),
+    /// wgpu ran out of memory.
+    #[error("Out of memory: {0}")]
+    OutOfMemory(String),
+    /// wgpu reported an internal error, which is not caused by the commands given to it.
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+impl From<wgpu::Error> for ExecuteError {
+    fn from(error: wgpu::Error) -> Self {
+        match error {
+            wgpu::Error::OutOfMemory { .. } => ExecuteError::OutOfMemory(error.to_string()),
+            wgpu::Error::Validation { description, .. } => ExecuteError::Validation(description),
+            wgpu::Error::Internal { description, .. } => ExecuteError::Internal(description),
+        }
+    }
 }
 
+/// An error from getting a device.
 #[derive(Error, Debug)]
 pub enum DeviceError {
+    /// The device could not be requested from the adapter.
     #[error("Could not create device as it could not be requested from the adapter: {0}")]
     CreationError(wgpu::RequestDeviceError),
+    /// No device was found.
     #[error("Found no devices")]
     DeviceNotFound,
-    #[error("Could not find the workgroup dimensions in the compute shader")]
-    ShaderWorkgroupSizeNotFound,
+    /// The device does not support one of the requested limits.
+    #[error("The device does not support a `{name}` of {requested} (it allows {allowed})")]
+    UnsupportedLimit {
+        /// The name of the limit.
+        name: &'static str,
+        /// The requested value of the limit.
+        requested: u64,
+        /// The value of the limit the device allows.
+        allowed: u64,
+    },
+    /// The device does not support all of the requested features.
+    #[error("The device does not support the requested features: {0}")]
+    MissingFeatures(Features),
 }
 
 /// Describes the limits imposed on the device.
@@ -55,6 +224,9 @@ pub enum LimitType {
     Default,
     /// Sets the limits to the [`downlevel_defaults`](https://docs.rs/wgpu/latest/wgpu/struct.Limits.html#method.downlevel_defaults) default in wgpu.
     Downlevel,
+    /// Sets the compute-related limits to the given limits, which is useful for reproducing
+    /// the constraints of another device. All other limits are as high as the device allows.
+    Custom(Limits),
 }
 
 mod private {
@@ -116,52 +288,170 @@ impl Device {
     pub(crate) async fn new(
         adapter: wgpu::Adapter,
         limit_type: LimitType,
+        features: Features,
     ) -> Result<Device, DeviceError> {
+        let missing = features.difference(Features::from(adapter.features()));
+        if !missing.is_empty() {
+            return Err(DeviceError::MissingFeatures(missing));
+        }
         let limits = match limit_type {
             LimitType::Highest => adapter.limits(),
             LimitType::Default => wgpu::Limits::default(),
             LimitType::Downlevel => wgpu::Limits::downlevel_defaults(),
+            LimitType::Custom(limits) => limits.apply_to(adapter.limits()),
         };
+        let mut unsupported = None;
+        limits.check_limits_with_fail_fn(&adapter.limits(), true, |name, requested, allowed| {
+            unsupported = Some(DeviceError::UnsupportedLimit {
+                name,
+                requested,
+                allowed,
+            });
+        });
+        if let Some(error) = unsupported {
+            return Err(error);
+        }
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    required_features: wgpu::Features::empty(),
+                    required_features: features.into(),
                     required_limits: limits.clone(),
                     memory_hints: wgpu::MemoryHints::Performance,
                 },
                 None,
             )
             .await
-            .map_err(|err| DeviceError::CreationError(err))?;
-        Ok(Self {
+            .map_err(DeviceError::CreationError)?;
+        let features = device.features();
+        let device = Arc::new(device);
+        let inner = DeviceInner {
             adapter,
+            poller: Poller::new(device.clone()),
             device,
             queue,
             limits: Limits::from(limits),
-            staging_buffer: None.into(),
-            staging_size: None.into(),
+            features: Features::from(features),
+            staging: Default::default(),
+            cache: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            error_scopes: Default::default(),
+        };
+        Ok(Self {
+            inner: Arc::new(inner),
         })
     }
     /// Gets the limits of the device.
     pub fn limits(&self) -> &Limits {
-        &self.limits
+        &self.inner.limits
+    }
+    /// Gets the features of the device, which are the features that were requested
+    /// when getting the device.
+    pub fn features(&self) -> Features {
+        self.inner.features
     }
     /// Gets the device's information.
     pub fn info(&self) -> DeviceInfo {
-        self.adapter.get_info()
+        self.inner.adapter.get_info()
     }
     // TODO: Allow for other shader sources too, such as SPIR-V and GLSL.
-    /// Creates a compute shader module. Will panic if there are errors in the compute shader.
-    pub fn create_shader_module(&self, shader: &str, entry_point: &str) -> ShaderModule {
-        ShaderModule::new(
-            self.device
-                .create_shader_module(wgpu::ShaderModuleDescriptor {
-                    label: None,
-                    source: wgpu::ShaderSource::Wgsl(shader.into()),
-                }),
+    /// Creates a compute shader module.
+    ///
+    /// Returns a [ShaderError] describing every problem found in the shader if it cannot
+    /// be compiled.
+    pub fn create_shader_module(
+        &self,
+        shader: &str,
+        entry_point: &str,
+    ) -> Result<ShaderModule, ShaderError> {
+        let module = self.compile_shader(shader)?;
+        Ok(ShaderModule::new(
+            module,
             entry_point,
-        )
+            reflect(shader, entry_point)?,
+            Arc::downgrade(&self.inner.cache),
+        ))
+    }
+    /// Compiles WGSL source into a wgpu shader module, returning the errors wgpu reports.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn compile_shader(&self, shader: &str) -> Result<wgpu::ShaderModule, ShaderError> {
+        let scope = self.inner.error_scopes.lock().unwrap();
+        self.inner
+            .device
+            .push_error_scope(wgpu::ErrorFilter::Validation);
+        let module = self
+            .inner
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(shader.into()),
+            });
+        let error = pollster::block_on(self.inner.device.pop_error_scope());
+        drop(scope);
+        let diagnostics: Vec<_> = pollster::block_on(module.get_compilation_info())
+            .messages
+            .iter()
+            .filter(|message| message.message_type == wgpu::CompilationMessageType::Error)
+            .map(|message| ShaderDiagnostic::new(shader, message))
+            .collect();
+        if !diagnostics.is_empty() {
+            return Err(ShaderError::Compilation(diagnostics));
+        }
+        if let Some(error) = error {
+            return Err(ShaderError::Compilation(vec![ShaderDiagnostic {
+                message: error.to_string(),
+                location: None,
+            }]));
+        }
+        Ok(module)
+    }
+    /// Compiles WGSL source into a wgpu shader module.
+    ///
+    /// On WebAssembly, the error scope and compilation info of wgpu can only be awaited, which
+    /// would block the browser. Instead, the shader is parsed and validated with naga before it
+    /// is passed to wgpu, so that invalid shaders are reported without ever reaching wgpu.
+    #[cfg(target_arch = "wasm32")]
+    fn compile_shader(&self, shader: &str) -> Result<wgpu::ShaderModule, ShaderError> {
+        let module = naga::front::wgsl::parse_str(shader).map_err(|error| {
+            ShaderError::Compilation(vec![ShaderDiagnostic {
+                message: error.emit_to_string(shader),
+                location: None,
+            }])
+        })?;
+        let features = self.features();
+        let capabilities = [
+            (features.shader_f64, naga::valid::Capabilities::FLOAT64),
+            (
+                features.shader_int64,
+                naga::valid::Capabilities::SHADER_INT64,
+            ),
+            (features.subgroups, naga::valid::Capabilities::SUBGROUP),
+            (
+                features.push_constants,
+                naga::valid::Capabilities::PUSH_CONSTANT,
+            ),
+        ]
+        .into_iter()
+        .filter(|(enabled, _)| *enabled)
+        .fold(
+            naga::valid::Capabilities::empty(),
+            |all, (_, capability)| all | capability,
+        );
+        naga::valid::Validator::new(naga::valid::ValidationFlags::all(), capabilities)
+            .validate(&module)
+            .map_err(|error| {
+                ShaderError::Compilation(vec![ShaderDiagnostic {
+                    message: error.emit_to_string(shader),
+                    location: None,
+                }])
+            })?;
+        Ok(self
+            .inner
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(shader.into()),
+            }))
     }
     /// Creates a compute shader module, but override the workgroup size of the entry point function
     /// in the compute shader at runtime.
@@ -170,7 +460,7 @@ impl Device {
         shader: &str,
         entry_point: &str,
         workgroup_dimensions: [u32; N],
-    ) -> Result<ShaderModule, DeviceError>
+    ) -> Result<ShaderModule, ShaderError>
     where
         [u32; N]: Dimensions,
     {
@@ -202,182 +492,476 @@ impl Device {
             }
         }
         if !modified {
-            return Err(DeviceError::ShaderWorkgroupSizeNotFound);
+            return Err(ShaderError::WorkgroupSizeNotFound);
         }
-        Ok(self.create_shader_module(&modified_shader, entry_point))
+        self.create_shader_module(&modified_shader, entry_point)
     }
-    /// Creates a buffer.
-    pub fn create_buffer<T: ShaderType + WriteInto>(
+    /// Creates a buffer holding elements of type `T`.
+    ///
+    /// Returns an error if the size of the buffer overflows or exceeds
+    /// [Limits::max_buffer_size].
+    pub fn create_buffer<T>(
         &self,
         label: Option<&str>,
         buffer_type: BufferType,
-        init_with: BufferInit<T>,
-    ) -> Buffer {
-        let buffer_contents = match init_with {
-            BufferInit::WithSize(size) => BufferContents::Size(size as u32 * size_of::<T>() as u32),
-            BufferInit::WithData(data) => match buffer_type {
-                BufferType::StorageBuffer { .. } => {
-                    let mut buffer = StorageBuffer::new(vec![]);
-                    buffer.write(&data).unwrap();
-                    BufferContents::Data(buffer.into_inner())
-                }
-                BufferType::UniformBuffer => {
-                    let mut buffer = UniformBuffer::new(vec![]);
-                    buffer.write(&data).unwrap();
-                    BufferContents::Data(buffer.into_inner())
-                }
-            },
+        init_with: BufferInit<'_, T>,
+    ) -> Result<Buffer<T>, BufferError>
+    where
+        T: ShaderType + ShaderSize + WriteInto,
+    {
+        let (len, buffer_contents) = match init_with {
+            BufferInit::WithSize(len) => {
+                let size = buffer_size::<T>(buffer_type, len)?;
+                self.check_buffer_size(size)?;
+                (len, BufferContents::Size(size))
+            }
+            BufferInit::WithData(data) => {
+                self.check_buffer_size(buffer_size::<T>(buffer_type, data.len())?)?;
+                (data.len(), BufferContents::Data(encode(buffer_type, data)?))
+            }
         };
-        let buffer = Buffer::new(label, self, buffer_type, buffer_contents);
-        if let BufferType::StorageBuffer { output: true, .. } = buffer_type {}
-        buffer
+        Ok(Buffer::new(
+            RawBuffer::new(label, self, buffer_type, buffer_contents),
+            len,
+        ))
+    }
+    /// Creates a buffer initialized with plain-old-data.
+    ///
+    /// This works like [Device::create_buffer], except that with [BufferInit::WithData], the
+    /// bytes of the elements are uploaded as-is instead of being serialized into a copy first.
+    /// The data is not kept around, so [RawBuffer::data] returns `None` for the buffer.
+    /// If the layout of `T` in the shader has padding that Rust does not have (like `u32`
+    /// elements in a uniform buffer, which are 16 bytes apart), the elements are serialized
+    /// as usual.
+    pub fn create_pod_buffer<T>(
+        &self,
+        label: Option<&str>,
+        buffer_type: BufferType,
+        init_with: BufferInit<'_, T>,
+    ) -> Result<Buffer<T>, BufferError>
+    where
+        T: ShaderType + ShaderSize + WriteInto + Pod,
+    {
+        match init_with {
+            // Empty data goes through `create_buffer`, which still makes room for one element.
+            BufferInit::WithData(data)
+                if !data.is_empty() && matches_pod_layout::<T>(buffer_type) =>
+            {
+                self.check_buffer_size(buffer_size::<T>(buffer_type, data.len())?)?;
+                Ok(Buffer::new(
+                    RawBuffer::from_bytes(label, self, buffer_type, bytemuck::cast_slice(data)),
+                    data.len(),
+                ))
+            }
+            init_with => self.create_buffer(label, buffer_type, init_with),
+        }
+    }
+    /// Creates an untyped buffer of the given size (in bytes), with all bytes set to zero.
+    ///
+    /// Returns an error if the size exceeds [Limits::max_buffer_size].
+    pub fn create_raw_buffer(
+        &self,
+        label: Option<&str>,
+        buffer_type: BufferType,
+        size: u64,
+    ) -> Result<RawBuffer, BufferError> {
+        self.check_buffer_size(size)?;
+        Ok(RawBuffer::new(
+            label,
+            self,
+            buffer_type,
+            BufferContents::Size(size),
+        ))
+    }
+    /// Checks that a buffer of the given size (in bytes) can be created on the device.
+    fn check_buffer_size(&self, size: u64) -> Result<(), BufferError> {
+        let limit = self.inner.limits.max_buffer_size;
+        if size > limit {
+            return Err(BufferError::TooLarge { size, limit });
+        }
+        Ok(())
     }
-    /// Gets the staging buffer of the device, which is necessary for getting data back
-    /// from the GPU.
-    pub(crate) fn staging(&self) -> &RefCell<Option<wgpu::Buffer>> {
-        &self.staging_buffer
+    /// Gets the pool of staging buffers of the device, which are necessary for getting data
+    /// back from the GPU.
+    pub(crate) fn staging(&self) -> &StagingPool {
+        &self.inner.staging
+    }
+    /// Gets the pipeline and bind group cache of the device.
+    pub(crate) fn cache(&self) -> &Mutex<PipelineCache> {
+        &self.inner.cache
     }
     /// Gets the device as a wgpu device.
     pub(crate) fn device(&self) -> &wgpu::Device {
-        &self.device
+        &self.inner.device
     }
     /// Gets the queue of the device.
     pub(crate) fn queue(&self) -> &wgpu::Queue {
-        &self.queue
-    }
-    /// Overrides the size of the staging buffer.
-    pub fn override_staging_size(&self, size: u32) {
-        let staging_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("shute staging buffer"),
-            size: size as u64,
-            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-            mapped_at_creation: false,
-        });
-        self.staging_buffer.replace(Some(staging_buffer));
-        self.staging_size.replace(Some(size));
+        &self.inner.queue
+    }
+    /// Gets the poller that resolves readbacks in the background.
+    pub(crate) fn poller(&self) -> &Poller {
+        &self.inner.poller
+    }
+    /// Allocates a staging buffer of the given size (in bytes) ahead of time.
+    ///
+    /// Every read from the GPU copies the data through a staging buffer, which is taken from
+    /// a pool of reusable staging buffers or allocated if none of them are large enough.
+    /// Reserving a staging buffer avoids that allocation when reading a buffer of up to
+    /// that size for the first time.
+    ///
+    /// Returns an error if the size exceeds [Limits::max_buffer_size].
+    pub fn reserve_staging(&self, size: u64) -> Result<(), BufferError> {
+        self.check_buffer_size(size)?;
+        self.inner.staging.reserve(&self.inner.device, size);
+        Ok(())
+    }
+    /// Starts recording a batch of commands, which are all submitted to the GPU at once.
+    ///
+    /// See [CommandBatch] for more information.
+    pub fn batch(&self) -> CommandBatch<'_> {
+        CommandBatch::new(self)
+    }
+    /// Copies `size` bytes from `source` (starting at `source_offset`) to `destination`
+    /// (starting at `destination_offset`). The copy is done entirely on the GPU.
+    ///
+    /// Returns an error if either range does not lie within its buffer, if an offset or the
+    /// size is not a multiple of `wgpu::COPY_BUFFER_ALIGNMENT` bytes, or if both buffers
+    /// are the same. Use `CommandBatch::copy_buffer` to copy as part of a batch.
+    pub fn copy_buffer(
+        &self,
+        source: &RawBuffer,
+        source_offset: u64,
+        destination: &RawBuffer,
+        destination_offset: u64,
+        size: u64,
+    ) -> Result<(), BufferError> {
+        let mut batch = self.batch();
+        batch.copy_buffer(source, source_offset, destination, destination_offset, size)?;
+        batch.submit_unchecked();
+        Ok(())
+    }
+    /// Sets all bytes of a buffer to zero. The buffer is cleared entirely on the GPU.
+    ///
+    /// Returns an error if the size of the buffer is not a multiple of
+    /// `wgpu::COPY_BUFFER_ALIGNMENT` bytes. Use `CommandBatch::clear_buffer` to clear a buffer
+    /// as part of a batch.
+    pub fn clear_buffer(&self, buffer: &RawBuffer) -> Result<(), BufferError> {
+        let mut batch = self.batch();
+        batch.clear_buffer(buffer)?;
+        batch.submit_unchecked();
+        Ok(())
     }
     /// Executes a compute shader with the given buffers and dispatch dimensions.
-    pub fn execute<const N: usize>(
+    ///
+    /// Buffers are either bound positionally with a `Vec<Vec<&mut RawBuffer>>` (the buffers in the
+    /// `n`th inner vector are bound to the bindings of `@group(n)` in the shader, in the order
+    /// of their `@binding` indices), or by the names of the global variables in the shader using
+    /// [Bindings](crate::Bindings). Every buffer is checked against the binding it is bound to,
+    /// as found through reflection of the shader module (see [ShaderModule::bindings]).
+    ///
+    /// The shader module is only borrowed, so a module can be executed any number of times
+    /// with different buffers and dispatch dimensions. The compute pipeline and bind groups
+    /// are cached, so repeatedly executing the same shader module with the same buffers only
+    /// records and submits the dispatch.
+    ///
+    /// Each call is submitted to the GPU on its own. Use [Device::batch] to submit several
+    /// dispatches at once.
+    ///
+    /// If the entry point of the shader calls `linear_workgroup_index` or
+    /// `linear_invocation_index` from [LINEAR_INDEX_WGSL](crate::LINEAR_INDEX_WGSL), a 1D
+    /// dispatch with more workgroups than [Limits::max_compute_workgroups_per_dimension] is
+    /// folded into a 2D or 3D grid, which may have a few more workgroups than requested. Such
+    /// shaders must skip indices past the end of the problem. Dispatches of other shaders are
+    /// never folded.
+    ///
+    /// Returns an error if the dispatch dimensions exceed
+    /// [Limits::max_compute_workgroups_per_dimension] (and cannot be folded), if the buffers
+    /// do not match the bindings of the shader, or if wgpu reports a validation error.
+    /// On WebAssembly, validation errors from wgpu go to its uncaptured error handler instead.
+    pub fn execute<B, const N: usize>(
         &self,
-        buffers: &Vec<Vec<&mut Buffer<'_>>>,
-        shader_module: ShaderModule,
+        buffers: &B,
+        shader_module: &ShaderModule,
         dispatch_dimensions: [u32; N],
-    ) where
+    ) -> Result<(), ExecuteError>
+    where
+        B: BufferBindings + ?Sized,
+        [u32; N]: Dimensions,
+    {
+        let mut batch = self.batch();
+        batch.execute(buffers, shader_module, dispatch_dimensions)?;
+        batch.submit()
+    }
+    /// Executes a compute shader over a problem of the given size, dispatching enough
+    /// workgroups to cover every element of the problem.
+    ///
+    /// The number of workgroups in each dimension is the problem size divided by the workgroup
+    /// size of the shader (see [ShaderModule::workgroup_size]), rounded up. As the last
+    /// workgroups may extend past the end of the problem, the shader should check that its
+    /// invocation IDs are within bounds.
+    ///
+    /// If the shader declares a `var<uniform> problem_size: vec3<u32>` (see
+    /// [PROBLEM_SIZE_BINDING](crate::PROBLEM_SIZE_BINDING)), the problem size is bound to it
+    /// automatically, with unused dimensions set to 1. The uniform must then be left out of
+    /// the given buffers.
+    pub fn execute_over<B, const N: usize>(
+        &self,
+        buffers: &B,
+        shader_module: &ShaderModule,
+        problem_dimensions: [u32; N],
+    ) -> Result<(), ExecuteError>
+    where
+        B: BufferBindings + ?Sized,
         [u32; N]: Dimensions,
     {
-        let (bind_group_layouts, bind_groups): (Vec<_>, Vec<_>) = buffers
+        let mut batch = self.batch();
+        batch.execute_over(buffers, shader_module, problem_dimensions)?;
+        batch.submit()
+    }
+    /// Gets the (possibly cached) compute pipeline and bind groups for dispatching
+    /// a shader module with the given buffers, which must be in the order of
+    /// `ShaderModule::bindings`.
+    pub(crate) fn prepare_dispatch(
+        &self,
+        buffers: &[&RawBuffer],
+        shader_module: &ShaderModule,
+    ) -> Result<(Arc<CachedPipeline>, Arc<[wgpu::BindGroup]>), ExecuteError> {
+        let mut cache = self.inner.cache.lock().unwrap();
+        let pipeline = cache.pipeline(shader_module.id(), || {
+            self.capture_errors(|| self.create_pipeline(shader_module))
+        })?;
+        let buffer_ids = buffers.iter().map(|buffer| buffer.id()).collect();
+        let bind_groups = cache.bind_groups(shader_module.id(), buffer_ids, || {
+            self.capture_errors(|| self.create_bind_groups(&pipeline, shader_module, buffers))
+        })?;
+        Ok((pipeline, bind_groups))
+    }
+    /// Runs `f`, returning any validation or out-of-memory error raised by wgpu in the meantime
+    /// instead of passing it on to the uncaptured error handler (which panics).
+    ///
+    /// Error scopes are shared by all threads using the device, so only one thread at a time
+    /// can capture errors.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn capture_errors<R>(&self, f: impl FnOnce() -> R) -> Result<R, ExecuteError> {
+        let _scope = self.inner.error_scopes.lock().unwrap();
+        self.inner
+            .device
+            .push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+        self.inner
+            .device
+            .push_error_scope(wgpu::ErrorFilter::Validation);
+        let result = f();
+        let validation_error = pollster::block_on(self.inner.device.pop_error_scope());
+        let memory_error = pollster::block_on(self.inner.device.pop_error_scope());
+        match validation_error.or(memory_error) {
+            Some(error) => Err(ExecuteError::from(error)),
+            None => Ok(result),
+        }
+    }
+    /// Runs `f`. Error scopes can only be awaited on WebAssembly, which would block the browser,
+    /// so errors raised by wgpu are passed on to the uncaptured error handler instead.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn capture_errors<R>(&self, f: impl FnOnce() -> R) -> Result<R, ExecuteError> {
+        Ok(f())
+    }
+    /// Checks that the dispatch dimensions are within the limits of the device.
+    ///
+    /// If `foldable` is set, a 1D dispatch that exceeds the limit is folded into a 2D or 3D grid
+    /// with at least as many workgroups, which is returned instead. Shaders recover the linear
+    /// workgroup index using the helpers in [LINEAR_INDEX_WGSL](crate::LINEAR_INDEX_WGSL).
+    pub(crate) fn validate_dispatch(
+        &self,
+        dispatch_dimensions: [u32; 3],
+        foldable: bool,
+    ) -> Result<[u32; 3], ExecuteError> {
+        let limit = self.inner.limits.max_compute_workgroups_per_dimension;
+        let dispatch_dimensions = match dispatch_dimensions {
+            [x, 1, 1] if foldable && x > limit => {
+                fold_dispatch(x, limit).unwrap_or(dispatch_dimensions)
+            }
+            _ => dispatch_dimensions,
+        };
+        if dispatch_dimensions
             .iter()
+            .any(|dimension| *dimension > limit)
+        {
+            return Err(ExecuteError::DispatchTooLarge {
+                dimensions: dispatch_dimensions,
+                limit,
+            });
+        }
+        Ok(dispatch_dimensions)
+    }
+    /// Creates a compute pipeline for a shader module, using the bind group layouts
+    /// given by the bindings of the shader.
+    fn create_pipeline(&self, shader_module: &ShaderModule) -> CachedPipeline {
+        let bind_group_layouts: Vec<_> = (0..shader_module.group_count() as u32)
             .map(|group| {
-                let layout_entries: Vec<_> = group
-                    .iter()
-                    .enumerate()
-                    .map(|(binding, buffer)| wgpu::BindGroupLayoutEntry {
-                        binding: binding as u32,
+                let layout_entries: Vec<_> = shader_module
+                    .group_bindings(group)
+                    .map(|binding| wgpu::BindGroupLayoutEntry {
+                        binding: binding.binding,
                         visibility: wgpu::ShaderStages::COMPUTE,
                         ty: wgpu::BindingType::Buffer {
-                            ty: match buffer.buffer_type() {
-                                BufferType::StorageBuffer { read_only, .. } => {
-                                    wgpu::BufferBindingType::Storage { read_only }
-                                }
-                                BufferType::UniformBuffer => wgpu::BufferBindingType::Uniform,
-                            },
+                            ty: binding.address_space.binding_type(),
                             has_dynamic_offset: false,
-                            min_binding_size: None,
+                            min_binding_size: NonZeroU64::new(binding.min_size),
                         },
                         count: None,
                     })
                     .collect();
-                let entries: Vec<_> = group
+                self.inner
+                    .device
+                    .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                        label: None,
+                        entries: &layout_entries[..],
+                    })
+            })
+            .collect();
+        let push_constant_ranges: Vec<_> = (shader_module.push_constant_size() > 0)
+            .then(|| wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::COMPUTE,
+                range: 0..shader_module.push_constant_size(),
+            })
+            .into_iter()
+            .collect();
+        let pipeline_layout =
+            self.inner
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: &bind_group_layouts
+                        .iter()
+                        .collect::<Vec<&wgpu::BindGroupLayout>>(),
+                    push_constant_ranges: &push_constant_ranges,
+                });
+        let pipeline =
+            self.inner
+                .device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: None,
+                    layout: Some(&pipeline_layout),
+                    module: shader_module.module(),
+                    entry_point: Some(shader_module.entry_point()),
+                    compilation_options: Default::default(),
+                    cache: None,
+                });
+        CachedPipeline {
+            bind_group_layouts,
+            pipeline,
+        }
+    }
+    /// Creates the bind groups for the given buffers (in the order of `ShaderModule::bindings`)
+    /// using the layouts of a pipeline.
+    fn create_bind_groups(
+        &self,
+        pipeline: &CachedPipeline,
+        shader_module: &ShaderModule,
+        buffers: &[&RawBuffer],
+    ) -> Vec<wgpu::BindGroup> {
+        pipeline
+            .bind_group_layouts
+            .iter()
+            .enumerate()
+            .map(|(group, layout)| {
+                let entries: Vec<_> = shader_module
+                    .bindings()
                     .iter()
-                    .enumerate()
+                    .zip(buffers)
+                    .filter(|(binding, _)| binding.group == group as u32)
                     .map(|(binding, buffer)| wgpu::BindGroupEntry {
-                        binding: binding as u32,
+                        binding: binding.binding,
                         resource: buffer.as_entire_binding(),
                     })
                     .collect();
-                let layout =
-                    self.device
-                        .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                            label: None,
-                            entries: &layout_entries[..],
-                        });
-                let group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: None,
-                    layout: &layout,
-                    entries: &entries[..],
-                });
-                (layout, group)
+                self.inner
+                    .device
+                    .create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: None,
+                        layout,
+                        entries: &entries[..],
+                    })
             })
-            .unzip();
-        let pipeline_layout = self
-            .device
-            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &bind_group_layouts
-                    .iter()
-                    .collect::<Vec<&wgpu::BindGroupLayout>>(),
-                push_constant_ranges: &[],
-            });
-        let pipeline = self
-            .device
-            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                label: None,
-                layout: Some(&pipeline_layout),
-                module: shader_module.module(),
-                entry_point: Some(shader_module.entry_point()),
-                compilation_options: Default::default(),
-                cache: None,
-            });
+            .collect()
+    }
+    /// Copies `size` bytes from a GPU-mapped buffer, starting at `offset`, to the start of
+    /// a staging buffer from the pool.
+    pub(crate) fn copy_to_staging(
+        &self,
+        buffer: &RawBuffer,
+        offset: u64,
+        size: u64,
+    ) -> StagingBuffer<'_> {
+        let mut staging = self.inner.staging.acquire(&self.inner.device, size);
+        staging.set_idle(false);
         let mut encoder = self
+            .inner
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        {
-            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: None,
-                timestamp_writes: None,
-            });
-            compute_pass.set_pipeline(&pipeline);
-            for (idx, bind_group) in bind_groups.iter().enumerate() {
-                compute_pass.set_bind_group(idx as u32, bind_group, &[]);
-            }
-            compute_pass.dispatch_workgroups(
-                dispatch_dimensions.x(),
-                dispatch_dimensions.y(),
-                dispatch_dimensions.z(),
-            );
-        }
-        if let Some(max_output_buffer_size) = buffers
-            .iter()
-            .flatten()
-            .filter(|buffer| buffer.output())
-            .map(|buffer| buffer.size())
-            .max()
-        {
-            if let Some(staging_size) = self.staging_size.borrow().as_ref() {
-                if *staging_size < max_output_buffer_size {
-                    self.override_staging_size(max_output_buffer_size);
-                    self.staging_size.replace(Some(max_output_buffer_size));
-                }
-            } else {
-                self.override_staging_size(max_output_buffer_size);
-                self.staging_size.replace(Some(max_output_buffer_size));
-            }
-        }
-        self.queue.submit(Some(encoder.finish()));
+        encoder.copy_buffer_to_buffer(buffer.buffer(), offset, &staging, 0, size);
+        self.inner.queue.submit(Some(encoder.finish()));
+        staging
     }
-    /// Copies the data from a GPU-mapped buffer to the staging buffer.
-    pub(crate) fn copy_to_staging(&self, buffer: &Buffer) {
-        let mut encoder = self
+    /// Processes finished GPU work without blocking, resolving any readbacks whose data is ready.
+    /// Returns `true` if the GPU has no more work left to do.
+    ///
+    /// Readbacks are resolved by a background thread as well, so this is only needed to
+    /// resolve them as soon as possible from a thread that is already busy polling. There is no
+    /// background thread on WebAssembly, where this has to be called to resolve readbacks on
+    /// backends other than WebGPU.
+    pub fn poll(&self) -> bool {
+        self.inner
             .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        if let Some(staging) = self.staging_buffer.borrow().as_ref() {
-            encoder.copy_buffer_to_buffer(buffer.buffer(), 0, staging, 0, buffer.size() as u64);
-        }
-        self.queue.submit(Some(encoder.finish()));
+            .poll(wgpu::Maintain::Poll)
+            .is_queue_empty()
     }
     /// Waits until the GPU queue is empty. That is, this method blocks further execution on the
     /// CPU side until the GPU is done doing all work given to it.
     pub fn synchronize(&self) {
-        self.device.poll(wgpu::Maintain::Wait);
+        self.inner.device.poll(wgpu::Maintain::Wait);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fold_dispatch;
+
+    #[test]
+    fn fold_dispatch_splits_into_2d() {
+        assert_eq!(fold_dispatch(65536, 65535), Some([32768, 2, 1]));
+        assert_eq!(fold_dispatch(131169, 65535), Some([43723, 3, 1]));
+        assert_eq!(fold_dispatch(65535 * 65535, 65535), Some([65535, 65535, 1]));
+    }
+
+    #[test]
+    fn fold_dispatch_splits_into_3d() {
+        assert_eq!(
+            fold_dispatch(65535 * 65535 + 1, 65535),
+            Some([65535, 32768, 2])
+        );
+        assert_eq!(fold_dispatch(u32::MAX, 65535), Some([65535, 32769, 2]));
+        assert_eq!(fold_dispatch(17, 4), Some([3, 3, 2]));
+    }
+
+    #[test]
+    fn fold_dispatch_rejects_too_many_workgroups() {
+        assert_eq!(fold_dispatch(65, 4), None);
+        assert_eq!(fold_dispatch(2, 1), None);
+    }
+
+    #[test]
+    fn fold_dispatch_covers_every_workgroup_within_limits() {
+        for limit in 1..=8u32 {
+            for workgroups in 1..=limit.pow(3) + 1 {
+                let Some([x, y, z]) = fold_dispatch(workgroups, limit) else {
+                    assert!(workgroups > limit.pow(3), "{workgroups} fit in {limit}^3");
+                    continue;
+                };
+                assert!(x <= limit && y <= limit && z <= limit);
+                assert!(x * y * z >= workgroups);
+                // The excess is less than one row per layer plus one workgroup per layer.
+                assert!(x * y * z < workgroups + y * z + z);
+            }
+        }
     }
 }
@@ -1,6 +1,7 @@
 use crate::{
+    adapter::Adapter,
     device::{Device, DeviceError, LimitType},
-    types::PowerPreference,
+    types::{Backends, Dx12Compiler, Features, PowerPreference},
 };
 
 /// Context for all other Shute objects.
@@ -9,43 +10,56 @@ use crate::{
 /// which is used for basically everything else, through the `autoselect` or `devices` methods.
 pub struct Instance {
     instance: wgpu::Instance,
+    backends: Backends,
 }
 
 impl Instance {
-    /// Create a new instance of Shute.
+    /// Create a new instance of Shute with the default configuration.
+    /// See [InstanceBuilder] for what that is.
     pub fn new() -> Instance {
-        Instance {
-            instance: wgpu::Instance::new(wgpu::InstanceDescriptor {
-                backends: wgpu::Backends::all(),
-                flags: if cfg!(debug_assertions) {
-                    wgpu::InstanceFlags::DEBUG
-                        | wgpu::InstanceFlags::VALIDATION
-                        | wgpu::InstanceFlags::GPU_BASED_VALIDATION
-                } else {
-                    wgpu::InstanceFlags::DISCARD_HAL_LABELS
-                },
-                dx12_shader_compiler: wgpu::Dx12Compiler::Fxc, // TODO: Somehow make this modifiable to Dxc
-                gles_minor_version: wgpu::Gles3MinorVersion::Automatic,
-            }),
-        }
+        Self::builder().build()
     }
-    /// Get all available devices on the system.
+    /// Get a builder for configuring a new instance.
+    pub fn builder() -> InstanceBuilder {
+        InstanceBuilder::default()
+    }
+    /// Get all available adapters on the system, without creating devices for them.
+    ///
+    /// This is much cheaper than [Instance::devices], and allows choosing the limits and
+    /// features of a device after inspecting what the adapters support.
     #[cfg(not(target_arch = "wasm32"))]
-    pub fn devices(&self) -> Vec<Result<Device, DeviceError>> {
+    pub fn adapters(&self) -> Vec<Adapter> {
         self.instance
-            .enumerate_adapters(wgpu::Backends::all())
+            .enumerate_adapters(self.backends)
             .into_iter()
             .filter(|adapter| adapter.get_info().device_type != wgpu::DeviceType::Other)
-            .map(|adapter| pollster::block_on(Device::new(adapter, LimitType::Highest)))
+            .map(Adapter::new)
+            .collect()
+    }
+    /// Get all available devices on the system, with the highest limits and the
+    /// given features.
+    ///
+    /// This creates a device for every adapter. Use [Instance::adapters] to list the adapters
+    /// without doing so. Devices that do not support all of the features are returned as
+    /// [DeviceError::MissingFeatures].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn devices(&self, features: Features) -> Vec<Result<Device, DeviceError>> {
+        self.adapters()
+            .into_iter()
+            .map(|adapter| pollster::block_on(adapter.request_device(LimitType::Highest, features)))
             .collect()
     }
     /// Automatically select a device (like a GPU) based on a power preference.
     /// The `limit_type` parameter will denote how the limits of the device are set.
     /// See [LimitType] for more information about that.
+    ///
+    /// Returns [DeviceError::MissingFeatures] if the selected device does not support all of
+    /// the requested features.
     pub async fn autoselect(
         &self,
         power_preference: PowerPreference,
         limit_type: LimitType,
+        features: Features,
     ) -> Result<Device, DeviceError> {
         let adapter = self
             .instance
@@ -55,8 +69,95 @@ impl Instance {
                 compatible_surface: None,
             })
             .await
-            .ok_or_else(|| DeviceError::DeviceNotFound)?;
-        Device::new(adapter, limit_type).await
+            .ok_or(DeviceError::DeviceNotFound)?;
+        Device::new(adapter, limit_type, features).await
+    }
+}
+
+/// A builder for an [Instance], obtained through `Instance::builder`.
+///
+/// By default, all backends are used with the FXC compiler for DX12, and debug builds enable
+/// debugging, validation and GPU-based validation. The configuration can be overridden at
+/// runtime with the environment variables that wgpu reads:
+///
+/// - `WGPU_BACKEND`: a comma-separated list of backends, like `vulkan,metal`.
+/// - `WGPU_DEBUG`, `WGPU_VALIDATION` and `WGPU_GPU_BASED_VALIDATION`: `0` to disable the flag,
+///   anything else to enable it.
+/// - `WGPU_DX12_COMPILER`: `fxc` or `dxc`.
+///
+/// Environment variables take precedence over the configuration set through the builder.
+pub struct InstanceBuilder {
+    backends: Backends,
+    debug: bool,
+    validation: bool,
+    gpu_based_validation: bool,
+    dx12_shader_compiler: Dx12Compiler,
+}
+
+impl InstanceBuilder {
+    /// Sets the backends (like Vulkan, Metal, or DX12) devices can be selected from.
+    pub fn backends(mut self, backends: Backends) -> Self {
+        self.backends = backends;
+        self
+    }
+    /// Sets whether to generate debug information, like labels, for the backend APIs.
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+    /// Sets whether the validation layers of the backend APIs are enabled.
+    pub fn validation(mut self, validation: bool) -> Self {
+        self.validation = validation;
+        self
+    }
+    /// Sets whether GPU-based validation is enabled, which validates shaders while they run
+    /// and is very slow. This has no effect unless validation is enabled as well.
+    pub fn gpu_based_validation(mut self, gpu_based_validation: bool) -> Self {
+        self.gpu_based_validation = gpu_based_validation;
+        self
+    }
+    /// Sets the shader compiler used by the DX12 backend.
+    pub fn dx12_shader_compiler(mut self, dx12_shader_compiler: Dx12Compiler) -> Self {
+        self.dx12_shader_compiler = dx12_shader_compiler;
+        self
+    }
+    /// Creates the instance, applying any overrides from environment variables.
+    pub fn build(self) -> Instance {
+        let backends = wgpu::util::backend_bits_from_env().unwrap_or(self.backends);
+        let mut flags = wgpu::InstanceFlags::empty();
+        flags.set(wgpu::InstanceFlags::DEBUG, self.debug);
+        flags.set(wgpu::InstanceFlags::VALIDATION, self.validation);
+        flags.set(
+            wgpu::InstanceFlags::GPU_BASED_VALIDATION,
+            self.gpu_based_validation,
+        );
+        let mut flags = flags.with_env();
+        flags.set(
+            wgpu::InstanceFlags::DISCARD_HAL_LABELS,
+            !flags.contains(wgpu::InstanceFlags::DEBUG),
+        );
+        Instance {
+            instance: wgpu::Instance::new(wgpu::InstanceDescriptor {
+                backends,
+                flags,
+                dx12_shader_compiler: wgpu::util::dx12_shader_compiler_from_env()
+                    .unwrap_or(self.dx12_shader_compiler),
+                gles_minor_version: wgpu::Gles3MinorVersion::Automatic,
+            }),
+            backends,
+        }
+    }
+}
+
+impl Default for InstanceBuilder {
+    fn default() -> Self {
+        Self {
+            backends: Backends::all(),
+            debug: cfg!(debug_assertions),
+            validation: cfg!(debug_assertions),
+            gpu_based_validation: cfg!(debug_assertions),
+            dx12_shader_compiler: Dx12Compiler::Fxc,
+        }
     }
 }
 
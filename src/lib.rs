@@ -3,13 +3,30 @@
 //! compute applications.
 #![warn(missing_docs)]
 
+mod adapter;
+mod batch;
+mod bindings;
 mod buffer;
+mod cache;
 mod device;
 mod instance;
+mod ping_pong;
+mod poller;
+mod shader;
+mod staging;
 mod types;
 
-pub use buffer::{Buffer, BufferInit, BufferType};
-pub use device::{Device, LimitType};
+pub use adapter::Adapter;
+pub use batch::{CommandBatch, Readback, Timings};
+pub use bindings::{Bindings, BufferBindings};
+pub use buffer::{Buffer, BufferError, BufferInit, BufferType, RawBuffer};
+pub use bytemuck::{Pod, Zeroable};
+pub use device::{Device, DeviceError, ExecuteError, LimitType};
 pub use encase::ShaderType;
-pub use instance::Instance;
+pub use instance::{Instance, InstanceBuilder};
+pub use ping_pong::PingPong;
+pub use shader::{
+    AddressSpace, LINEAR_INDEX_WGSL, PROBLEM_SIZE_BINDING, ShaderBinding, ShaderDiagnostic,
+    ShaderError, ShaderLocation, ShaderModule,
+};
 pub use types::*;
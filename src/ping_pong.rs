@@ -0,0 +1,46 @@
+use std::mem;
+
+/// A pair of buffers that swap roles after every pass of an iterative algorithm.
+///
+/// Kernels that read from one buffer and write to another (like stencils, reductions, or
+/// relaxations) read from the front buffer and write to the back buffer. Calling
+/// [PingPong::swap] after each pass makes the results of that pass the input of the next,
+/// so the latest results are always in the front buffer.
+///
+/// Bind the pair by name with `Bindings::bind_ping_pong`, or positionally through
+/// [PingPong::pair_mut].
+pub struct PingPong<B> {
+    front: B,
+    back: B,
+}
+
+impl<B> PingPong<B> {
+    /// Creates a pair with the given front and back buffers.
+    pub fn new(front: B, back: B) -> Self {
+        Self { front, back }
+    }
+    /// Gets the front buffer, which holds the input of the next pass.
+    pub fn front(&self) -> &B {
+        &self.front
+    }
+    /// Gets the back buffer, which the next pass writes its output to.
+    pub fn back(&self) -> &B {
+        &self.back
+    }
+    /// Gets mutable references to the front and back buffers, in that order.
+    ///
+    /// This allows binding the pair positionally, as in
+    /// `let (front, back) = buffers.pair_mut(); vec![vec![&mut **front, &mut **back]]`.
+    pub fn pair_mut(&mut self) -> (&mut B, &mut B) {
+        (&mut self.front, &mut self.back)
+    }
+    /// Swaps the front and back buffers, making the output of the last pass the input
+    /// of the next.
+    pub fn swap(&mut self) {
+        mem::swap(&mut self.front, &mut self.back);
+    }
+    /// Takes the front and back buffers out of the pair, in that order.
+    pub fn into_inner(self) -> (B, B) {
+        (self.front, self.back)
+    }
+}
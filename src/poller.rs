@@ -0,0 +1,96 @@
+use std::sync::{Arc, Condvar, Mutex};
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread::JoinHandle;
+
+/// Polls a device on a background thread while buffers are waiting to be mapped, so that
+/// awaiting a readback does not block the thread it is awaited on.
+///
+/// The thread sleeps whenever no buffers are waiting to be mapped. Threads cannot be spawned
+/// on WebAssembly, where the browser resolves mappings on its own (or `Device::poll` is used),
+/// so no thread is started there.
+pub(crate) struct Poller {
+    state: Arc<PollerState>,
+    #[cfg(not(target_arch = "wasm32"))]
+    thread: Option<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct PollerState {
+    status: Mutex<PollerStatus>,
+    wake: Condvar,
+}
+
+#[derive(Default)]
+struct PollerStatus {
+    pending: usize,
+    stopped: bool,
+}
+
+/// Marks a buffer as waiting to be mapped for as long as it is alive.
+///
+/// Move it into the callback given to `wgpu::BufferSlice::map_async`, so that it is dropped
+/// once the mapping has finished.
+pub(crate) struct PendingMap {
+    state: Arc<PollerState>,
+}
+
+impl Poller {
+    /// Starts polling the given device in the background.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn new(device: Arc<wgpu::Device>) -> Self {
+        let state = Arc::new(PollerState::default());
+        let thread = {
+            let state = state.clone();
+            std::thread::spawn(move || {
+                loop {
+                    {
+                        let mut status = state.status.lock().unwrap();
+                        while status.pending == 0 && !status.stopped {
+                            status = state.wake.wait(status).unwrap();
+                        }
+                        if status.stopped {
+                            break;
+                        }
+                    }
+                    device.poll(wgpu::Maintain::Wait);
+                }
+            })
+        };
+        Self {
+            state,
+            thread: Some(thread),
+        }
+    }
+    /// Creates a poller that only keeps track of the buffers waiting to be mapped.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn new(_device: Arc<wgpu::Device>) -> Self {
+        Self {
+            state: Arc::new(PollerState::default()),
+        }
+    }
+    /// Registers a buffer that is about to be mapped, waking up the background thread.
+    pub(crate) fn begin(&self) -> PendingMap {
+        self.state.status.lock().unwrap().pending += 1;
+        self.state.wake.notify_one();
+        PendingMap {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl Drop for PendingMap {
+    fn drop(&mut self) {
+        self.state.status.lock().unwrap().pending -= 1;
+    }
+}
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        self.state.status.lock().unwrap().stopped = true;
+        self.state.wake.notify_one();
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
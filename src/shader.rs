@@ -0,0 +1,520 @@
+use std::{
+    fmt,
+    ops::Range,
+    sync::{Arc, Mutex, OnceLock, Weak},
+};
+
+use thiserror::Error;
+
+use crate::{
+    Device,
+    buffer::{BufferContents, BufferType, RawBuffer},
+    cache::{PipelineCache, next_id},
+};
+
+/// A compute shader module. Used in `Device::execute`.
+///
+/// The buffer bindings declared in the shader are reflected when the module is created,
+/// and are used to lay out and validate the buffers it is executed with.
+///
+/// A shader module only needs to be compiled once, as it can be executed any number of times.
+/// Cloning a shader module is cheap, as clones share the same underlying module through
+/// reference counting. Compute pipelines created from a shader module are cached by the
+/// device that created it for as long as the module (or any of its clones) is alive.
+#[derive(Clone)]
+pub struct ShaderModule {
+    inner: Arc<ShaderModuleInner>,
+}
+
+struct ShaderModuleInner {
+    id: u64,
+    module: wgpu::ShaderModule,
+    entry_point: String,
+    reflection: Reflection,
+    cache: Weak<Mutex<PipelineCache>>,
+    problem_size: OnceLock<RawBuffer>,
+}
+
+impl Drop for ShaderModuleInner {
+    fn drop(&mut self) {
+        if let Some(cache) = self.cache.upgrade() {
+            cache.lock().unwrap().evict_module(self.id);
+        }
+    }
+}
+
+impl ShaderModule {
+    /// Create a new shader module.
+    ///
+    /// Preferably, create shader modules using `Device::create_shader_module` and
+    /// `Device::create_shader_module_with_workgroup_size` instead. This method is used there.
+    pub(crate) fn new(
+        module: wgpu::ShaderModule,
+        entry_point: &str,
+        reflection: Reflection,
+        cache: Weak<Mutex<PipelineCache>>,
+    ) -> Self {
+        Self {
+            inner: Arc::new(ShaderModuleInner {
+                id: next_id(),
+                module,
+                entry_point: entry_point.to_string(),
+                reflection,
+                cache,
+                problem_size: OnceLock::new(),
+            }),
+        }
+    }
+    /// Get the identifier of the shader module, which is shared between clones.
+    pub(crate) fn id(&self) -> u64 {
+        self.inner.id
+    }
+    /// Get just the shader module (without entry point).
+    pub(crate) fn module(&self) -> &wgpu::ShaderModule {
+        &self.inner.module
+    }
+    /// Get the entry point of the compute shader.
+    pub fn entry_point(&self) -> &String {
+        &self.inner.entry_point
+    }
+    /// Get the buffer bindings declared in the compute shader, sorted by group and binding index.
+    pub fn bindings(&self) -> &[ShaderBinding] {
+        &self.inner.reflection.bindings
+    }
+    /// Get the workgroup size of the entry point, given by `@workgroup_size`. Dimensions that
+    /// are not specified in the shader are 1.
+    pub fn workgroup_size(&self) -> [u32; 3] {
+        self.inner.reflection.workgroup_size
+    }
+    /// Get the size in bytes of the push constants used by the entry point, which is zero if it
+    /// uses none. Push constants are set using `CommandBatch::set_push_constants`.
+    pub fn push_constant_size(&self) -> u32 {
+        self.inner.reflection.push_constant_size
+    }
+    /// Check if the entry point calls one of the helpers from [LINEAR_INDEX_WGSL], in which
+    /// case large 1D dispatches are folded into a 2D or 3D grid (see `Device::execute`).
+    pub(crate) fn uses_linear_index(&self) -> bool {
+        self.inner.reflection.uses_linear_index
+    }
+    /// Get the uniform buffer that `Device::execute_over` binds the problem size to, creating it
+    /// on first use. Returns `None` if the shader does not declare a problem size uniform.
+    ///
+    /// The buffer is shared by all dispatches of the module, so that its bind groups stay
+    /// cached. It holds a `vec3<u32>`, which takes up 16 bytes in a uniform buffer.
+    pub(crate) fn problem_size_buffer(&self, device: &Device) -> Option<&RawBuffer> {
+        self.problem_size_binding()?;
+        Some(self.inner.problem_size.get_or_init(|| {
+            RawBuffer::new(
+                Some("shute problem size"),
+                device,
+                BufferType::UniformBuffer,
+                BufferContents::Size(16),
+            )
+        }))
+    }
+    /// Get the binding of the uniform that `Device::execute_over` binds the problem size to,
+    /// if the shader declares one.
+    pub(crate) fn problem_size_binding(&self) -> Option<&ShaderBinding> {
+        self.bindings().iter().find(|binding| {
+            binding.name.as_deref() == Some(PROBLEM_SIZE_BINDING)
+                && binding.address_space == AddressSpace::Uniform
+        })
+    }
+    /// Get the number of bind groups the compute shader expects, which is one more than
+    /// the highest group index used.
+    pub fn group_count(&self) -> usize {
+        self.bindings()
+            .last()
+            .map_or(0, |binding| binding.group as usize + 1)
+    }
+    /// Get the buffer bindings declared in the compute shader in the given group.
+    pub fn group_bindings(&self, group: u32) -> impl Iterator<Item = &ShaderBinding> {
+        self.bindings()
+            .iter()
+            .filter(move |binding| binding.group == group)
+    }
+}
+
+/// The address space of a buffer binding in a shader, along with its access mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AddressSpace {
+    /// A uniform buffer, declared with `var<uniform>`.
+    Uniform,
+    /// A storage buffer, declared with `var<storage, read>` or `var<storage, read_write>`.
+    Storage {
+        /// Denotes if the shader can only read from the buffer.
+        read_only: bool,
+    },
+}
+
+impl AddressSpace {
+    /// Get the wgpu binding type corresponding to the address space.
+    pub(crate) fn binding_type(self) -> wgpu::BufferBindingType {
+        match self {
+            AddressSpace::Uniform => wgpu::BufferBindingType::Uniform,
+            AddressSpace::Storage { read_only } => wgpu::BufferBindingType::Storage { read_only },
+        }
+    }
+}
+
+/// A buffer binding declared in a compute shader, found through shader reflection.
+#[derive(Clone, Debug)]
+pub struct ShaderBinding {
+    /// The name of the global variable in the shader.
+    pub name: Option<String>,
+    /// The bind group index, given by `@group`.
+    pub group: u32,
+    /// The binding index within the bind group, given by `@binding`.
+    pub binding: u32,
+    /// The address space and access mode of the binding.
+    pub address_space: AddressSpace,
+    /// The minimum size (in bytes) of a buffer bound to the binding. For runtime-sized arrays,
+    /// this assumes the array has a single element.
+    pub min_size: u64,
+}
+
+/// The name of the uniform that `Device::execute_over` binds the problem size to.
+pub const PROBLEM_SIZE_BINDING: &str = "problem_size";
+
+/// WGSL helpers for recovering linear indices in shaders whose 1D dispatches may be folded
+/// into a 2D or 3D grid by `Device::execute`, which is only done for entry points that call
+/// one of these helpers. Append them to the shader source, as in
+/// `format!("{source}\n{LINEAR_INDEX_WGSL}")`.
+///
+/// - `linear_workgroup_index(workgroup_id, num_workgroups)` gets the index of the workgroup
+///   as if the dispatch had not been folded.
+/// - `linear_invocation_index(workgroup_id, num_workgroups, local_invocation_index, workgroup_len)`
+///   gets the index of the invocation as if the dispatch had not been folded, where
+///   `workgroup_len` is the number of invocations in a workgroup.
+pub const LINEAR_INDEX_WGSL: &str = "
+fn linear_workgroup_index(workgroup_id: vec3<u32>, num_workgroups: vec3<u32>) -> u32 {
+    return workgroup_id.x + num_workgroups.x * (workgroup_id.y + num_workgroups.y * workgroup_id.z);
+}
+
+fn linear_invocation_index(
+    workgroup_id: vec3<u32>,
+    num_workgroups: vec3<u32>,
+    local_invocation_index: u32,
+    workgroup_len: u32,
+) -> u32 {
+    return linear_workgroup_index(workgroup_id, num_workgroups) * workgroup_len + local_invocation_index;
+}
+";
+
+/// The information about a compute shader found through shader reflection.
+pub(crate) struct Reflection {
+    /// The buffer bindings, sorted by group and binding index.
+    pub(crate) bindings: Vec<ShaderBinding>,
+    /// The workgroup size of the entry point.
+    pub(crate) workgroup_size: [u32; 3],
+    /// Whether the entry point calls one of the helpers from [LINEAR_INDEX_WGSL].
+    pub(crate) uses_linear_index: bool,
+    /// The size in bytes of the push constants used by the entry point.
+    pub(crate) push_constant_size: u32,
+}
+
+/// The names of the functions defined by [LINEAR_INDEX_WGSL].
+const LINEAR_INDEX_FUNCTIONS: [&str; 2] = ["linear_workgroup_index", "linear_invocation_index"];
+
+/// Reflects the buffer bindings and workgroup size of a WGSL compute shader, checking that
+/// it has a compute entry point with the given name.
+pub(crate) fn reflect(source: &str, entry_point: &str) -> Result<Reflection, ShaderError> {
+    let module = naga::front::wgsl::parse_str(source).map_err(|error| {
+        ShaderError::Compilation(vec![ShaderDiagnostic {
+            message: error.message().to_string(),
+            location: None,
+        }])
+    })?;
+    let Some((entry_index, entry)) =
+        module.entry_points.iter().enumerate().find(|(_, entry)| {
+            entry.name == entry_point && entry.stage == naga::ShaderStage::Compute
+        })
+    else {
+        return Err(ShaderError::EntryPointNotFound(entry_point.to_string()));
+    };
+    // Validation analyzes which global variables each entry point uses, including through
+    // the functions it calls. Only the bindings used by the entry point need to be bound.
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|error| {
+        ShaderError::Compilation(vec![ShaderDiagnostic {
+            message: error.as_inner().to_string(),
+            location: None,
+        }])
+    })?;
+    let usage = info.get_entry_point(entry_index);
+    let mut bindings: Vec<_> = module
+        .global_variables
+        .iter()
+        .filter(|(handle, _)| !usage[*handle].is_empty())
+        .filter_map(|(_, variable)| {
+            let address_space = match variable.space {
+                naga::AddressSpace::Uniform => AddressSpace::Uniform,
+                naga::AddressSpace::Storage { access } => AddressSpace::Storage {
+                    read_only: !access.contains(naga::StorageAccess::STORE),
+                },
+                _ => return None,
+            };
+            let binding = variable.binding.as_ref()?;
+            Some(ShaderBinding {
+                name: variable.name.clone(),
+                group: binding.group,
+                binding: binding.binding,
+                address_space,
+                min_size: module.types[variable.ty].inner.size(module.to_ctx()) as u64,
+            })
+        })
+        .collect();
+    bindings.sort_by_key(|binding| (binding.group, binding.binding));
+    // A shader has at most one push constant variable per entry point.
+    let push_constant_size = module
+        .global_variables
+        .iter()
+        .find(|(handle, variable)| {
+            variable.space == naga::AddressSpace::PushConstant && !usage[*handle].is_empty()
+        })
+        .map_or(0, |(_, variable)| {
+            module.types[variable.ty].inner.size(module.to_ctx())
+        });
+    Ok(Reflection {
+        bindings,
+        workgroup_size: entry.workgroup_size,
+        uses_linear_index: calls_any(&module, &entry.function.body, &LINEAR_INDEX_FUNCTIONS),
+        push_constant_size,
+    })
+}
+
+/// Checks if a block of statements calls a function with one of the given names, either
+/// directly or through the functions it calls.
+fn calls_any(module: &naga::Module, block: &naga::Block, names: &[&str]) -> bool {
+    block.iter().any(|statement| match statement {
+        naga::Statement::Call { function, .. } => {
+            let function = &module.functions[*function];
+            function
+                .name
+                .as_deref()
+                .is_some_and(|name| names.contains(&name))
+                || calls_any(module, &function.body, names)
+        }
+        naga::Statement::Block(block) => calls_any(module, block, names),
+        naga::Statement::If { accept, reject, .. } => {
+            calls_any(module, accept, names) || calls_any(module, reject, names)
+        }
+        naga::Statement::Switch { cases, .. } => cases
+            .iter()
+            .any(|case| calls_any(module, &case.body, names)),
+        naga::Statement::Loop {
+            body, continuing, ..
+        } => calls_any(module, body, names) || calls_any(module, continuing, names),
+        _ => false,
+    })
+}
+
+/// An error from creating a shader module.
+#[derive(Error, Debug)]
+pub enum ShaderError {
+    /// The shader source could not be compiled.
+    #[error("Failed to compile shader module:\n{}", format_diagnostics(.0))]
+    Compilation(Vec<ShaderDiagnostic>),
+    /// The shader has no compute entry point with the given name.
+    #[error("Could not find the compute entry point `{0}` in the shader")]
+    EntryPointNotFound(String),
+    /// The workgroup size of the entry point could not be found in the shader source.
+    #[error("Could not find the workgroup dimensions in the compute shader")]
+    WorkgroupSizeNotFound,
+}
+
+fn format_diagnostics(diagnostics: &[ShaderDiagnostic]) -> String {
+    diagnostics
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A single error message from compiling a shader module.
+#[derive(Debug, Clone)]
+pub struct ShaderDiagnostic {
+    /// The error message.
+    pub message: String,
+    /// Where in the shader source the error is, if known.
+    pub location: Option<ShaderLocation>,
+}
+
+/// The location of an error in the source of a shader module.
+#[derive(Debug, Clone)]
+pub struct ShaderLocation {
+    /// 1-based line number.
+    pub line: u32,
+    /// 1-based column (in characters) of the start of the span.
+    pub column: u32,
+    /// Byte range of the erroneous code in the shader source.
+    pub span: Range<usize>,
+    /// The line of shader source containing the start of the span.
+    pub snippet: String,
+}
+
+impl ShaderDiagnostic {
+    /// Converts a wgpu compilation message into a diagnostic for the given shader source.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn new(source: &str, message: &wgpu::CompilationMessage) -> Self {
+        let location = message.location.map(|location| {
+            let start = (location.offset as usize).min(source.len());
+            let line_start = source[..start].rfind('\n').map_or(0, |idx| idx + 1);
+            let line_end = source[start..]
+                .find('\n')
+                .map_or(source.len(), |idx| start + idx);
+            ShaderLocation {
+                line: location.line_number,
+                column: source[line_start..start].chars().count() as u32 + 1,
+                span: start..(start + location.length as usize).min(source.len()),
+                snippet: source[line_start..line_end].to_string(),
+            }
+        });
+        // The message from wgpu is a full report including the source snippet,
+        // so only its headline is kept.
+        let headline = message
+            .message
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or_default();
+        let headline = headline
+            .strip_prefix("Shader '")
+            .and_then(|rest| rest.split_once("' "))
+            .map_or(headline, |(_, rest)| rest);
+        Self {
+            message: headline.to_string(),
+            location,
+        }
+    }
+}
+
+impl fmt::Display for ShaderDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(
+                f,
+                "{}:{}: {}\n    {}\n    {}^",
+                location.line,
+                location.column,
+                self.message,
+                location.snippet,
+                " ".repeat(location.column as usize - 1)
+            ),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LINEAR_INDEX_WGSL, reflect};
+
+    #[test]
+    fn reflect_finds_linear_index() {
+        let source = format!(
+            "
+            @compute @workgroup_size(64)
+            fn main(
+                @builtin(workgroup_id) id: vec3<u32>,
+                @builtin(num_workgroups) n: vec3<u32>,
+            ) {{
+                let index = linear_workgroup_index(id, n);
+            }}
+            {LINEAR_INDEX_WGSL}"
+        );
+        let reflection = reflect(&source, "main").unwrap();
+        assert!(reflection.uses_linear_index);
+        assert_eq!(reflection.workgroup_size, [64, 1, 1]);
+    }
+
+    #[test]
+    fn reflect_finds_linear_index_through_calls() {
+        let source = format!(
+            "
+            fn index(id: vec3<u32>, n: vec3<u32>, local: u32) -> u32 {{
+                return linear_invocation_index(id, n, local, 64u);
+            }}
+            @compute @workgroup_size(64)
+            fn main(
+                @builtin(workgroup_id) id: vec3<u32>,
+                @builtin(num_workgroups) n: vec3<u32>,
+                @builtin(local_invocation_index) local: u32,
+            ) {{
+                if (id.x > 0u) {{
+                    let index = index(id, n, local);
+                }}
+            }}
+            {LINEAR_INDEX_WGSL}"
+        );
+        assert!(reflect(&source, "main").unwrap().uses_linear_index);
+    }
+
+    #[test]
+    fn reflect_without_linear_index() {
+        // Taking the `num_workgroups` builtin alone, as in a grid-stride loop, does not make
+        // the dispatch foldable.
+        let source = format!(
+            "
+            @compute @workgroup_size(1)
+            fn main(
+                @builtin(global_invocation_id) id: vec3<u32>,
+                @builtin(num_workgroups) n: vec3<u32>,
+            ) {{
+                for (var i = id.x; i < 1024u; i += n.x) {{}}
+            }}
+            {LINEAR_INDEX_WGSL}"
+        );
+        assert!(!reflect(&source, "main").unwrap().uses_linear_index);
+    }
+
+    #[test]
+    fn reflect_only_bindings_of_entry_point() {
+        let source = "
+            @group(0) @binding(0) var<storage, read_write> a: array<u32>;
+            @group(0) @binding(1) var<uniform> scale: u32;
+            @group(1) @binding(0) var<storage, read_write> b: array<u32>;
+            fn scaled(value: u32) -> u32 { return value * scale; }
+            @compute @workgroup_size(1)
+            fn main_a() { a[0] = scaled(a[0]); }
+            @compute @workgroup_size(1)
+            fn main_b() { b[0] = 1u; }
+        ";
+        let names = |entry_point| {
+            reflect(source, entry_point)
+                .unwrap()
+                .bindings
+                .into_iter()
+                .map(|binding| binding.name.unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names("main_a"), ["a", "scale"]);
+        assert_eq!(names("main_b"), ["b"]);
+    }
+
+    #[test]
+    fn reflect_push_constant_size() {
+        let source = "
+            struct Params { scale: u32, offset: vec2<u32> }
+            var<push_constant> params: Params;
+            @compute @workgroup_size(1)
+            fn with_params() { let scale = params.scale; }
+            @compute @workgroup_size(1)
+            fn without_params() {}
+        ";
+        assert_eq!(
+            reflect(source, "with_params").unwrap().push_constant_size,
+            16
+        );
+        assert_eq!(
+            reflect(source, "without_params")
+                .unwrap()
+                .push_constant_size,
+            0
+        );
+    }
+}
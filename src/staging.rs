@@ -0,0 +1,95 @@
+use std::{ops::Deref, sync::Mutex};
+
+/// The number of idle staging buffers kept around for reuse. When more buffers are returned
+/// to the pool, the smallest ones are freed.
+const MAX_IDLE_BUFFERS: usize = 4;
+
+/// A pool of map-readable staging buffers, which are used for copying data back from the GPU.
+///
+/// Every read gets a staging buffer of its own, so any number of reads can be in flight at
+/// once. Buffers are returned to the pool once a read is done with them and reused by later
+/// reads that fit in them.
+#[derive(Default)]
+pub(crate) struct StagingPool {
+    idle: Mutex<Vec<wgpu::Buffer>>,
+}
+
+impl StagingPool {
+    /// Takes the smallest idle staging buffer of at least `size` bytes from the pool, or
+    /// creates a new one if there is none.
+    pub(crate) fn acquire(&self, device: &wgpu::Device, size: u64) -> StagingBuffer<'_> {
+        let buffer = {
+            let mut idle = self.idle.lock().unwrap();
+            idle.iter()
+                .enumerate()
+                .filter(|(_, buffer)| buffer.size() >= size)
+                .min_by_key(|(_, buffer)| buffer.size())
+                .map(|(idx, _)| idx)
+                .map(|idx| idle.swap_remove(idx))
+        };
+        StagingBuffer {
+            pool: self,
+            buffer: Some(buffer.unwrap_or_else(|| create_staging_buffer(device, size))),
+            idle: true,
+        }
+    }
+    /// Adds a new idle staging buffer of the given size to the pool.
+    pub(crate) fn reserve(&self, device: &wgpu::Device, size: u64) {
+        self.release(create_staging_buffer(device, size));
+    }
+    /// Returns an idle staging buffer to the pool.
+    fn release(&self, buffer: wgpu::Buffer) {
+        let mut idle = self.idle.lock().unwrap();
+        idle.push(buffer);
+        if idle.len() > MAX_IDLE_BUFFERS {
+            idle.sort_by_key(|buffer| std::cmp::Reverse(buffer.size()));
+            idle.truncate(MAX_IDLE_BUFFERS);
+        }
+    }
+}
+
+fn create_staging_buffer(device: &wgpu::Device, size: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("shute staging buffer"),
+        size,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    })
+}
+
+/// A staging buffer taken from a [StagingPool], which is returned to the pool when dropped.
+pub(crate) struct StagingBuffer<'a> {
+    pool: &'a StagingPool,
+    buffer: Option<wgpu::Buffer>,
+    idle: bool,
+}
+
+impl StagingBuffer<'_> {
+    /// Marks whether the staging buffer is idle, that is, not mapped and not the destination
+    /// of a recorded copy whose data has not been read yet. Mark it as not idle as soon as
+    /// a copy into it is recorded.
+    ///
+    /// Buffers that are not idle when dropped are freed instead of being returned to the pool,
+    /// as a pending copy or mapping may still use them.
+    pub(crate) fn set_idle(&mut self, idle: bool) {
+        self.idle = idle;
+    }
+}
+
+impl Deref for StagingBuffer<'_> {
+    type Target = wgpu::Buffer;
+
+    fn deref(&self) -> &Self::Target {
+        self.buffer.as_ref().unwrap()
+    }
+}
+
+impl Drop for StagingBuffer<'_> {
+    fn drop(&mut self) {
+        if let Some(buffer) = self.buffer.take()
+            && self.idle
+        {
+            self.pool.release(buffer);
+        }
+    }
+}
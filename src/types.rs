@@ -1,39 +1,31 @@
+use std::fmt;
+
 /// Alias of [`wgpu::PowerPreference`](https://docs.rs/wgpu/latest/wgpu/enum.PowerPreference.html).
 ///
 /// Power preference when autoselecting a device with `Instance::autoselect`.
 pub type PowerPreference = wgpu::PowerPreference;
 
+/// Alias of [`wgpu::Backends`](https://docs.rs/wgpu/latest/wgpu/struct.Backends.html).
+///
+/// The backends an instance selects devices from, set with `InstanceBuilder::backends`.
+pub type Backends = wgpu::Backends;
+
+/// Alias of [`wgpu::Backend`](https://docs.rs/wgpu/latest/wgpu/enum.Backend.html).
+///
+/// The backend an adapter is accessed through, given by `Adapter::backend`.
+pub type Backend = wgpu::Backend;
+
+/// Alias of [`wgpu::Dx12Compiler`](https://docs.rs/wgpu/latest/wgpu/enum.Dx12Compiler.html).
+///
+/// The shader compiler used by the DX12 backend, set with
+/// `InstanceBuilder::dx12_shader_compiler`.
+pub type Dx12Compiler = wgpu::Dx12Compiler;
+
 /// Alias of [`wgpu::AdapterInfo`](https://docs.rs/wgpu/latest/wgpu/struct.AdapterInfo.html).
 ///
 /// Contains information about a device.
 pub type DeviceInfo = wgpu::AdapterInfo;
 
-/// A compute shader module. Used in `Device::execute`.
-pub struct ShaderModule {
-    module: wgpu::ShaderModule,
-    entry_point: String,
-}
-impl ShaderModule {
-    /// Create a new shader module.
-    ///
-    /// Preferably, create shader modules using `Device::create_shader_module` and
-    /// `Device::create_shader_module_with_workgroup_size` instead. This method is used there.
-    pub(crate) fn new(module: wgpu::ShaderModule, entry_point: &str) -> Self {
-        Self {
-            module,
-            entry_point: entry_point.to_string(),
-        }
-    }
-    /// Get just the shader module (without entry point).
-    pub(crate) fn module(&self) -> &wgpu::ShaderModule {
-        &self.module
-    }
-    /// Get the entry point of the compute shader.
-    pub fn entry_point(&self) -> &String {
-        &self.entry_point
-    }
-}
-
 /// Limits for a device.
 ///
 /// This is a trimmed-down version of
@@ -42,7 +34,12 @@ impl ShaderModule {
 ///
 /// This can be used to, for example, dynamically set workgroup or dispatch dimensions
 /// for optimization purposes.
-#[derive(Debug)]
+///
+/// Custom limits can be requested with `LimitType::Custom`, starting from one of the presets
+/// (`Limits::default()`, `Limits::downlevel()`, or the limits of an existing device) and
+/// overriding individual limits, as in
+/// `Limits { max_storage_buffer_binding_size: 64 << 20, ..Limits::downlevel() }`.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Limits {
     /// Amount of bind groups that can be attached to a pipeline at the same time.
     /// Defaults to 4. Higher is “better”.
@@ -93,6 +90,49 @@ pub struct Limits {
     pub min_subgroup_size: u32,
     /// Maximal number of invocations in a subgroup. Lower is “better”.
     pub max_subgroup_size: u32,
+    /// Maximum size in bytes of the push constants of a pipeline. Only used with the
+    /// `push_constants` feature. Defaults to 0. Higher is “better”.
+    pub max_push_constant_size: u32,
+}
+
+impl Limits {
+    /// Gets the [`downlevel_defaults`](https://docs.rs/wgpu/latest/wgpu/struct.Limits.html#method.downlevel_defaults)
+    /// limits of wgpu, as used by `LimitType::Downlevel`.
+    pub fn downlevel() -> Self {
+        wgpu::Limits::downlevel_defaults().into()
+    }
+    /// Replaces the compute-related limits in a set of wgpu limits with these limits.
+    pub(crate) fn apply_to(&self, limits: wgpu::Limits) -> wgpu::Limits {
+        wgpu::Limits {
+            max_bind_groups: self.max_bind_groups,
+            max_bindings_per_bind_group: self.max_bindings_per_bind_group,
+            max_dynamic_uniform_buffers_per_pipeline_layout: self
+                .max_dynamic_uniform_buffers_per_pipeline_layout,
+            max_dynamic_storage_buffers_per_pipeline_layout: self
+                .max_dynamic_storage_buffers_per_pipeline_layout,
+            max_uniform_buffer_binding_size: self.max_uniform_buffer_binding_size,
+            max_storage_buffer_binding_size: self.max_storage_buffer_binding_size,
+            max_buffer_size: self.max_buffer_size,
+            max_compute_workgroup_storage_size: self.max_compute_workgroup_storage_size,
+            max_compute_invocations_per_workgroup: self.max_compute_invocations_per_workgroup,
+            max_compute_workgroup_size_x: self.max_compute_workgroup_size_x,
+            max_compute_workgroup_size_y: self.max_compute_workgroup_size_y,
+            max_compute_workgroup_size_z: self.max_compute_workgroup_size_z,
+            max_compute_workgroups_per_dimension: self.max_compute_workgroups_per_dimension,
+            min_subgroup_size: self.min_subgroup_size,
+            max_subgroup_size: self.max_subgroup_size,
+            max_push_constant_size: self.max_push_constant_size,
+            ..limits
+        }
+    }
+}
+
+/// The [wgpu default limits](https://docs.rs/wgpu/latest/wgpu/struct.Limits.html#method.default),
+/// as used by `LimitType::Default`.
+impl Default for Limits {
+    fn default() -> Self {
+        wgpu::Limits::default().into()
+    }
 }
 
 impl From<wgpu::Limits> for Limits {
@@ -115,6 +155,97 @@ impl From<wgpu::Limits> for Limits {
             max_compute_workgroups_per_dimension: limits.max_compute_workgroups_per_dimension,
             min_subgroup_size: limits.min_subgroup_size,
             max_subgroup_size: limits.max_subgroup_size,
+            max_push_constant_size: limits.max_push_constant_size,
+        }
+    }
+}
+
+/// Optional features of a device.
+///
+/// This is a trimmed-down version of
+/// [`wgpu::Features`](https://docs.rs/wgpu/latest/wgpu/struct.Features.html)
+/// with only compute-related features. Features are requested when getting a device through
+/// `Instance::autoselect` or `Instance::devices`, and a device only has the features that
+/// were requested, even if it supports more.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Features {
+    /// Allows shaders to use the `f16` type, after declaring `enable f16;`.
+    pub shader_f16: bool,
+    /// Allows shaders to use the `f64` type.
+    pub shader_f64: bool,
+    /// Allows shaders to use the `i64` and `u64` types.
+    pub shader_int64: bool,
+    /// Allows shaders to use subgroup operations and builtins.
+    pub subgroups: bool,
+    /// Allows shaders to use a `var<push_constant>`, which is set using
+    /// `CommandBatch::set_push_constants`. The size of the push constants is limited by
+    /// `Limits::max_push_constant_size`, which is zero unless using `LimitType::Highest`
+    /// or custom limits.
+    pub push_constants: bool,
+    /// Allows timing dispatches on the GPU using `CommandBatch::time_dispatches`.
+    pub timestamp_queries: bool,
+}
+
+impl Features {
+    /// Creates a set of features with none of the features enabled.
+    pub fn empty() -> Self {
+        Self::default()
+    }
+    /// Gets the features in this set that are not in `other`.
+    pub fn difference(self, other: Features) -> Features {
+        Features::from(wgpu::Features::from(self) - wgpu::Features::from(other))
+    }
+    /// Checks if none of the features are enabled.
+    pub fn is_empty(self) -> bool {
+        self == Self::empty()
+    }
+    /// Gets the names of the enabled features.
+    fn names(self) -> Vec<&'static str> {
+        [
+            (self.shader_f16, "shader_f16"),
+            (self.shader_f64, "shader_f64"),
+            (self.shader_int64, "shader_int64"),
+            (self.subgroups, "subgroups"),
+            (self.push_constants, "push_constants"),
+            (self.timestamp_queries, "timestamp_queries"),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, name)| enabled.then_some(name))
+        .collect()
+    }
+}
+
+impl fmt::Display for Features {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.names().join(", "))
+    }
+}
+
+impl From<wgpu::Features> for Features {
+    fn from(features: wgpu::Features) -> Self {
+        Self {
+            shader_f16: features.contains(wgpu::Features::SHADER_F16),
+            shader_f64: features.contains(wgpu::Features::SHADER_F64),
+            shader_int64: features.contains(wgpu::Features::SHADER_INT64),
+            subgroups: features.contains(wgpu::Features::SUBGROUP),
+            push_constants: features.contains(wgpu::Features::PUSH_CONSTANTS),
+            timestamp_queries: features.contains(wgpu::Features::TIMESTAMP_QUERY),
         }
     }
 }
+
+impl From<Features> for wgpu::Features {
+    fn from(features: Features) -> Self {
+        [
+            (features.shader_f16, wgpu::Features::SHADER_F16),
+            (features.shader_f64, wgpu::Features::SHADER_F64),
+            (features.shader_int64, wgpu::Features::SHADER_INT64),
+            (features.subgroups, wgpu::Features::SUBGROUP),
+            (features.push_constants, wgpu::Features::PUSH_CONSTANTS),
+            (features.timestamp_queries, wgpu::Features::TIMESTAMP_QUERY),
+        ]
+        .into_iter()
+        .filter(|(enabled, _)| *enabled)
+        .fold(wgpu::Features::empty(), |all, (_, feature)| all | feature)
+    }
+}